@@ -1,682 +1,4657 @@
 use std::{
     fs,
+    io::{self, Read, Write, BufReader, BufWriter},
+    hash::{Hash, Hasher},
+    collections::{HashMap, VecDeque, hash_map::DefaultHasher},
     fmt::{self, Debug},
-    path::PathBuf,
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+    ops::Range,
+    num::NonZeroUsize,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
     f32::consts
 };
 
 use image::{
     Rgb32FImage,
-    Rgba32FImage,
     RgbImage
 };
 
-use crate::{Point2, Lab, LabImage, LabaImage};
+use fastrand::Rng;
+
+use crate::{
+    Point2,
+    Lab,
+    Laba,
+    LabImage,
+    LabaImage,
+    GradientImage,
+    Rendered,
+    Segments,
+    Shadow,
+    Stroke,
+    EdgeMode,
+    RotationSampling,
+    ScaleFilter,
+    ScaleDecay,
+    AngleMode,
+    TransparencyMode,
+    Background,
+    BackgroundInit,
+    Optimizer,
+    ImageSource,
+    BlendMode,
+    Commit,
+    colors::{self, ColorConversion, Blending},
+    lab_image::{GenericImage, Resamplable}
+};
 
 
-const SQRT_DISTANCE: bool = true;
 
+#[derive(Clone)]
 pub struct CollagerConfig
 {
     pub steps: u32,
+    // the background is a single color so it needs way less than a placement
+    pub background_steps: u32,
+    pub background_starts: u32,
     pub amount: u32,
+    // placements per megapixel of the input, replaces amount if its set
+    pub auto_amount: Option<f32>,
     pub starts: u32,
+    // random placements tried before annealing, the best one is the starting state
+    pub greedy_candidates: u32,
+    // recently accepted states that cant be accepted again, 0 turns it off
+    // rejections only depend on the visited states so a run stays just as repeatable
+    pub tabu_size: usize,
+    // neighbors evaluated in parallel every step, the best one is the move
+    pub neighbor_batch: usize,
+    // the hill climber only ever keeps its best state so this only matters for annealing
+    pub commit: Commit,
+    pub optimizer: Optimizer,
     pub starting_temperature: f32,
+    // lowest temperature of the schedule, so the last steps still move a little
+    pub temperature_floor: f32,
+    // neighbors get accepted when theyre at most temperature^gamma worse
+    pub acceptance_gamma: f32,
+    // equal energy neighbors dont get accepted at zero temperature, so the end doesnt drift
+    pub strict_acceptance: bool,
+    // a placement stops annealing after this many steps without the best improving
+    // by more than patience_epsilon of itself, 0 always uses every step
+    pub patience: u32,
+    pub patience_epsilon: f32,
+    // the steps of a placement get split into this many separate coolings from the start
+    pub cooling_cycles: u32,
+    pub flat_background: bool,
+    pub background_color: Option<Lab>,
+    pub background: Background,
+    pub background_init: BackgroundInit,
+    // grid size of the blocks background
+    pub background_cells: u32,
+    // every placement starts from the params of the one before it instead of random ones
+    pub warm_start: bool,
+    pub margin: u32,
     pub allow_scaling: bool,
+    pub allow_nonuniform_scaling: bool,
+    pub scale_min: f32,
+    pub scale_max: f32,
+    pub scale_decay: ScaleDecay,
+    // scaled placements with fewer pixels than this r never accepted
+    pub min_placement_pixels: u32,
     pub allow_rotation: bool,
+    pub angle_mode: AngleMode,
+    // how many angles the quantized angle mode picks from
+    pub angle_steps: u32,
     pub allow_hue: bool,
+    pub allow_blend_modes: bool,
     pub allow_transparency: bool,
-    pub debug: bool
+    // pixels at or below this alpha r never made more transparent
+    pub transparency_lower_bound: f32,
+    pub transparency_mode: TransparencyMode,
+    // little images without any pixel at least this opaque dont get placed
+    pub min_alpha: f32,
+    // no pixel of a placed little image gets more opaque than this
+    pub max_tile_opacity: f32,
+    pub scaling_unlock: f32,
+    pub rotation_unlock: f32,
+    pub hue_unlock: f32,
+    pub transparency_unlock: f32,
+    pub color_weight: f32,
+    pub gradient_weight: f32,
+    pub ssim_weight: f32,
+    // pulls the placed pixels towards the mean color of the region theyre in, 0 turns it off
+    pub segment_weight: f32,
+    // size of the color buckets the regions r made of
+    pub segment_step: f32,
+    // most placements any cell of a grid_cells by grid_cells grid over the input can get
+    pub grid_cap: Option<u32>,
+    pub grid_cells: u32,
+    // penalty for every earlier placement under a pixel that a new placement changes
+    pub overlap_weight: f32,
+    // exponent applied to the squared per pixel distance, 0.5 is the euclidean distance
+    pub distance_exponent: f32,
+    pub shadow: Option<Shadow>,
+    // outline drawn around each placement
+    pub stroke: Option<Stroke>,
+    // darkening towards the corners of the finished collage, 0 turns it off
+    pub vignette: f32,
+    // output alpha is how much the placements cover, the rest gets cut away
+    pub die_cut: bool,
+    pub edge_mode: EdgeMode,
+    pub rotation_sampling: RotationSampling,
+    pub scale_filter: ScaleFilter,
+    // pixels r always stored as Lab but hold coordinates of this space
+    pub conversion: ColorConversion,
+    // set by ctrl-c, collaging, stamping and voronoi stop early and keep what they have
+    // main only installs it on unix with the interrupt feature, other builds silently get none
+    pub stop: Option<&'static AtomicBool>,
+    // side of the square parts of the target that get annealed separately
+    pub tile_size: Option<u32>,
+    pub tile_overlap: u32,
+    // energies get measured on a copy of the canvas this many times smaller
+    pub energy_downscale: u32,
+    // only every nth pixel gets compared while annealing, the final error still uses all of them
+    pub energy_stride: usize,
+    // every start gets its seed before the threads split up, so the thread count
+    // doesnt change the results
+    pub threads: Option<usize>,
+    // random if none, same seed and config always give the same collage
+    pub seed: Option<u64>,
+    // the whole run state gets saved here after every placement
+    pub checkpoint: Option<PathBuf>,
+    // prints how much each placement lowered the error at the end
+    pub energy_report: bool,
+    // median and percentile errors next to the mean, needs a sort of every pixel
+    pub stats: bool,
+    pub debug: bool,
+    // debug images only get saved for every nth placement
+    pub debug_every: u32,
+    // debug images get the bounds and outline of the last placement drawn on them
+    pub debug_boxes: bool
 }
 
-pub struct Collager
+// how much a single placement lowered the error of the canvas
+struct Contribution
 {
-    config: CollagerConfig,
-    image: LabImage
+    placed: u32,
+    index: Option<usize>,
+    reduction: f64
 }
 
-impl Collager
+impl fmt::Display for Contribution
 {
-    pub fn new(config: CollagerConfig, image: Rgb32FImage) -> Self
-    {
-        Self{config, image: image.into()}
-    }
-
-    pub fn collage(&self, images: &[Rgba32FImage]) -> RgbImage
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
     {
-        let images: Vec<_> = images.iter().map(|image|
-        {
-            LabaImage::from(image.clone())
-        }).collect();
-
-        let images = &images;
-
-        let background = BackgroundAnnealable::new(&self.image);
-
-        let mut output = Annealer::new(background, 30.0).anneal(self.config.steps).applied();
+        write!(f, "placement {}", self.placed)?;
 
-        let tenth = (self.config.amount / 10).max(1);
-        for i in 0..self.config.amount
+        if let Some(index) = self.index
         {
-            if i % tenth == 0
-            {
-                let percentage = i as f32 / self.config.amount as f32 * 100.0;
-
-                println!("progress: {percentage:.1}%");
-            }
-
-            let params = ||
-            {
-                Node::cons(
-                    IndexParam::random(images),
-                    Node::cons(
-                        ScaleParam::random(self.config.allow_scaling),
-                        Node::cons(
-                            HueParam::random(self.config.allow_hue),
-                            Node::cons(
-                                TransparencyParam::random(self.config.allow_transparency),
-                                Node::cons(
-                                    AngleParam::random(self.config.allow_rotation),
-                                    Node::cons(
-                                        PositionParam::random(),
-                                        Node::nil()))))))
-            };
-
-            let anneal = ||
-            {
-                let annealable = ImageAnnealable::new(&self.image, &output, params());
-
-                Annealer::new(annealable, self.config.starting_temperature)
-                    .anneal_with_energy(self.config.steps)
-            };
-
-            output = (0..self.config.starts).map(|_|
-            {
-                anneal()
-            }).min_by(|a, b|
-            {
-                a.energy.partial_cmp(&b.energy).unwrap()
-            }).expect("steps must be at least 1").state.applied();
-
-            if self.config.debug
-            {
-                let debug_dir = PathBuf::from("test");
-
-                if !debug_dir.exists()
-                {
-                    fs::create_dir(&debug_dir).unwrap();
-                }
-
-                let image_name = format!("image{i}.png");
-                output.clone().to_rgb().save(debug_dir.join(image_name)).unwrap();
-            }
+            write!(f, " (image {index})")?;
         }
 
-        let final_error = UsefulOps::image_difference(
-            self.image.pixels().copied(),
-            output.pixels().copied()
-        );
-
-        let error_per_pixel = final_error / (self.image.width() * self.image.height()) as f32;
-
-        println!("final error per pixel: {error_per_pixel:.3}");
-
-        output.to_rgb()
-    }
-}
-
-// if lisp is so good why havent they made lisp 2?
-#[derive(Clone)]
-struct Node<T, C>(T, C);
-
-impl<T, C> Node<T, C>
-{
-    pub fn cons(value: T, other: C) -> Self
-    {
-        Self(value, other)
+        write!(f, ": {:.1}", self.reduction)
     }
 }
 
-impl Node<(), ()>
+#[derive(Debug, Clone, Copy)]
+pub struct Progress
 {
-    pub fn nil() -> () {}
+    pub placed: u32,
+    // wall clock time per placement so far, resumed placements not included
+    pub average_time: Duration,
+    pub amount: u32,
+    pub energy: f64,
+    // summed over every start of the last placement
+    pub stats: AnnealStats,
+    // what got placed, render_plan with every one of these redraws the collage
+    pub info: Option<PlacementInfo>
 }
 
-trait NodeTrait
+impl Progress
 {
-    type Item;
-    type Child;
-
-    // the word applies makes no sense here but i dont wanna be confused
-    fn applies(&self, state: ImageState) -> ImageState;
-    fn neighbors(self, temperature: f32) -> Self;
+    // guessed from how long the placements so far took
+    pub fn eta(&self) -> Duration
+    {
+        self.average_time * self.amount.saturating_sub(self.placed)
+    }
 }
 
-impl NodeTrait for ()
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlacementInfo
 {
-    type Item = ();
-    type Child = ();
-
-    fn applies(&self, state: ImageState) -> ImageState {state}
-    fn neighbors(self, _temperature: f32) -> () {}
+    pub index: Option<usize>,
+    pub scale: Option<Point2<f32>>,
+    pub hue: Option<Lab>,
+    pub transparency: Option<f32>,
+    pub angle: Option<f32>,
+    pub blend: Option<BlendMode>,
+    // fraction of the image size
+    pub position: Option<Point2<f32>>
 }
 
-impl<T: Paramable, C: NodeTrait> NodeTrait for Node<T, C>
+impl PlacementInfo
 {
-    type Item = T;
-    type Child = C;
-
-    fn applies(&self, state: ImageState) -> ImageState
-    {
-        self.1.applies(self.0.apply(state))
-    }
-
-    fn neighbors(self, temperature: f32) -> Self
+    // nearly identical placements hash the same
+    pub fn quantized_hash(&self) -> u64
     {
-        Self(self.0.neighbor(temperature), self.1.neighbors(temperature))
-    }
-}
+        let quantized = UsefulOps::quantized;
 
-struct UsefulOps;
-
-impl UsefulOps
-{
-    fn float_changed(v: f32, temperature: f32) -> f32
-    {
-        let delta = fastrand::f32() * 2.0 - 1.0;
+        let mut hasher = DefaultHasher::new();
 
-        v + (delta * temperature)
-    }
+        self.index.hash(&mut hasher);
+        self.scale.map(|x| [x.x, x.y].map(quantized)).hash(&mut hasher);
+        self.hue.map(|x| [x.l, x.a, x.b].map(quantized)).hash(&mut hasher);
+        self.transparency.map(quantized).hash(&mut hasher);
+        self.angle.map(quantized).hash(&mut hasher);
+        self.blend.hash(&mut hasher);
+        self.position.map(|x| [x.x, x.y].map(quantized)).hash(&mut hasher);
 
-    fn image_difference(a: impl Iterator<Item=Lab>, b: impl Iterator<Item=Lab>) -> f32
-    {
-        a.zip(b).map(|(original, changed)|
-        {
-            if SQRT_DISTANCE
-            {
-                original.distance(changed).sqrt()
-            } else
-            {
-                original.distance(changed)
-            }
-        }).sum()
+        hasher.finish()
     }
 }
 
-struct ImageState
+struct Placement
 {
     image: LabImage,
-    add_image: Option<LabaImage>,
-    angle: Option<f32>,
+    energy: f64,
+    stats: AnnealStats,
+    info: PlacementInfo,
+    touched: Option<(Point2<i32>, Point2<i32>)>,
+    corners: Option<[Point2<f32>; 4]>,
+    // the cutout alpha after this placement
+    alpha: Option<LabImage>
 }
 
-// parametable? who cares its just a word
-trait Paramable
+pub enum Resume
 {
-    fn apply(&self, state: ImageState) -> ImageState;
-    fn neighbor(self, temperature: f32) -> Self;
+    // a previous output, everything else about the run starts from nothing
+    Image{image: LabImage, placed: u32},
+    // exactly where a previous run stopped
+    Checkpoint(Box<Checkpoint>)
 }
 
-#[derive(Clone)]
-struct IndexParam<'a>
+// everything that changes between placements, continuing from it gives
+// the same result as never stopping
+pub struct Checkpoint
 {
-    images: &'a [LabaImage],
-    index: usize
+    placed: u32,
+    seed: u64,
+    canvas: LabImage,
+    tracking: Tracking
 }
 
-impl<'a> IndexParam<'a>
+impl Checkpoint
 {
-    fn random(images: &'a [LabaImage]) -> Self
+    const MAGIC: &'static [u8; 4] = b"ACCP";
+    const VERSION: u32 = 3;
+
+    pub fn placed(&self) -> u32
     {
-        Self{index: fastrand::usize(0..images.len()), images}
+        self.placed
     }
-}
 
-impl<'a> Paramable for IndexParam<'a>
-{
-    // i would love imagestate to contain a & but rust lifetimes r cancer
-    // and i cant figure out if i can constrain self lifetime
-    // to contain the & lifetime of the imagestate :/
-    fn apply(&self, mut state: ImageState) -> ImageState
+    fn write_to(&self, mut writer: impl Write) -> io::Result<()>
     {
-        state.add_image = Some(self.images[self.index].clone());
+        writer.write_all(Self::MAGIC)?;
+        writer.write_all(&Self::VERSION.to_le_bytes())?;
 
-        state
-    }
+        writer.write_all(&self.placed.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
 
-    fn neighbor(self, temperature: f32) -> Self
-    {
-        if fastrand::f32() < temperature
+        Self::write_lab(&mut writer, &self.canvas)?;
+
+        Self::write_option(&mut writer, self.tracking.mirror.as_ref(), Self::write_lab)?;
+        Self::write_option(&mut writer, self.tracking.coverage.as_ref(), |writer, coverage|
         {
-            Self{index: fastrand::usize(0..self.images.len()), ..self}
-        } else
+            Self::write_gradient(writer, &coverage.full)?;
+            Self::write_option(writer, coverage.mirror.as_ref(), Self::write_gradient)
+        })?;
+        Self::write_option(&mut writer, self.tracking.grid.as_ref(), |writer, grid|
         {
-            self
-        }
-    }
-}
-
-#[derive(Clone)]
-struct ScaleParam(Option<Point2<f32>>);
+            writer.write_all(&(grid.counts.len() as u32).to_le_bytes())?;
 
-impl ScaleParam
-{
-    fn random(allow: bool) -> Self
-    {
-        Self(allow.then(||
+            grid.counts.iter().try_for_each(|count| writer.write_all(&count.to_le_bytes()))
+        })?;
+        Self::write_option(&mut writer, self.tracking.cutout.as_ref(), |writer, cutout|
         {
-            Point2{
-                x: fastrand::f32() + 0.5,
-                y: fastrand::f32() + 0.5
-            }
-        }))
+            Self::write_lab(writer, &cutout.alpha)?;
+            Self::write_lab(writer, &cutout.background)
+        })?;
+        Self::write_option(&mut writer, self.tracking.warm.as_ref(), Self::write_info)?;
+
+        writer.flush()
     }
-}
 
-impl Paramable for ScaleParam
-{
-    fn apply(&self, mut state: ImageState) -> ImageState
+    // the grid only has its counts saved, the rest comes from the config
+    fn read_from(mut reader: impl Read, grid: Option<PlacementGrid>) -> io::Result<Self>
     {
-        if let Some(scale) = self.0
-        {
-            let raw = state.add_image.as_ref().unwrap();
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
 
-            let original_size = Point2{x: raw.width(), y: raw.height()};
-            let size = (original_size.map(|x| x as f32) * scale).map(|x| x as usize);
+        if &magic != Self::MAGIC
+        {
+            return Err(Self::invalid("not a checkpoint file"));
+        }
 
-            state.add_image = Some(raw.resized_nearest(size));
+        let version = Self::read_u32(&mut reader)?;
+        if version != Self::VERSION
+        {
+            return Err(Self::invalid(format!("unknown checkpoint version {version}")));
         }
 
-        state
-    }
+        let placed = Self::read_u32(&mut reader)?;
 
-    fn neighbor(self, temperature: f32) -> Self
-    {
-        let change = |v, scale|
+        let mut seed = [0; 8];
+        reader.read_exact(&mut seed)?;
+        let seed = u64::from_le_bytes(seed);
+
+        let canvas = Self::read_lab(&mut reader)?;
+
+        let mirror = Self::read_option(&mut reader, Self::read_lab)?;
+        let coverage = Self::read_option(&mut reader, |reader|
         {
-            UsefulOps::float_changed(v, temperature * scale)
-        };
+            let full = Self::read_gradient(reader)?;
+            let mirror = Self::read_option(reader, Self::read_gradient)?;
 
-        Self(self.0.map(|value| value.map(|x| change(x, 0.5).max(0.05))))
-    }
-}
+            Ok(Coverage{full, mirror})
+        })?;
+        let counts = Self::read_option(&mut reader, |reader|
+        {
+            let len = Self::read_u32(reader)?;
 
-#[derive(Clone)]
-struct HueParam(Option<Lab>);
+            (0..len).map(|_| Self::read_u32(reader)).collect::<io::Result<Vec<_>>>()
+        })?;
 
-impl HueParam
-{
-    fn random(allow: bool) -> Self
-    {
-        let r = |value|
+        let grid = match (grid, counts)
         {
-            (fastrand::f32() * 2.0 - 1.0) * value
+            (None, None) => None,
+            (Some(grid), Some(counts)) if grid.counts.len() == counts.len() =>
+            {
+                Some(PlacementGrid{counts, ..grid})
+            },
+            _ => return Err(Self::invalid("checkpoint has a different placement grid"))
         };
 
-        Self(allow.then(||
+        let cutout = Self::read_option(&mut reader, |reader|
         {
-            Lab{l: r(25.0), a: r(50.0), b: r(50.0)}
-        }))
+            let alpha = Self::read_lab(reader)?;
+            let background = Self::read_lab(reader)?;
+
+            Ok(Cutout{alpha, background})
+        })?;
+
+        let warm = Self::read_option(&mut reader, Self::read_info)?;
+
+        Ok(Self{placed, seed, canvas, tracking: Tracking{mirror, coverage, grid, cutout, warm}})
     }
-}
 
-impl Paramable for HueParam
-{
-    fn apply(&self, mut state: ImageState) -> ImageState
+    fn invalid(message: impl Into<String>) -> io::Error
     {
-        if let Some(hue) = self.0
-        {
-            state.add_image.as_mut().unwrap().pixels_mut().for_each(|pixel|
-            {
-                pixel.l += hue.l;
-                pixel.a += hue.a;
-                pixel.b += hue.b;
-            });
-        }
-
-        state
+        io::Error::new(io::ErrorKind::InvalidData, message.into())
     }
 
-    fn neighbor(self, temperature: f32) -> Self
+    fn write_option<W: Write, T>(
+        writer: &mut W,
+        value: Option<&T>,
+        f: impl FnOnce(&mut W, &T) -> io::Result<()>
+    ) -> io::Result<()>
     {
-        let change = |v, scale|
-        {
-            UsefulOps::float_changed(v, temperature * scale)
-        };
+        writer.write_all(&[value.is_some() as u8])?;
 
-        Self(self.0.map(|value| value.map(|x| change(x, 20.0))))
+        value.map(|value| f(writer, value)).unwrap_or(Ok(()))
     }
-}
 
-#[derive(Clone)]
-struct TransparencyParam(Option<f32>);
+    fn read_option<R: Read, T>(
+        reader: &mut R,
+        f: impl FnOnce(&mut R) -> io::Result<T>
+    ) -> io::Result<Option<T>>
+    {
+        let mut exists = [0];
+        reader.read_exact(&mut exists)?;
 
-impl TransparencyParam
-{
-    fn random(allow: bool) -> Self
+        (exists[0] != 0).then(|| f(reader)).transpose()
+    }
+
+    fn write_floats<W: Write>(
+        writer: &mut W,
+        size: Point2<usize>,
+        floats: impl Iterator<Item=f32>
+    ) -> io::Result<()>
     {
-        Self(allow.then(||
-        {
-            fastrand::f32() * 2.0 - 1.0
-        }))
+        writer.write_all(&(size.x as u32).to_le_bytes())?;
+        writer.write_all(&(size.y as u32).to_le_bytes())?;
+
+        floats.map(f32::to_le_bytes).try_for_each(|bytes| writer.write_all(&bytes))
     }
-}
 
-impl Paramable for TransparencyParam
-{
-    fn apply(&self, mut state: ImageState) -> ImageState
+    fn read_floats<R: Read>(reader: &mut R, channels: usize) -> io::Result<(Point2<usize>, Vec<f32>)>
     {
-        if let Some(transparency) = self.0
-        {
-            state.add_image.as_mut().unwrap().pixels_mut().for_each(|pixel|
-            {
-                let lower_bound = 0.05;
+        let width = Self::read_u32(reader)? as usize;
+        let height = Self::read_u32(reader)? as usize;
 
-                if pixel.alpha > lower_bound
-                {
-                    pixel.alpha = (pixel.alpha + transparency).clamp(lower_bound, 1.0);
-                }
-            });
-        }
+        let mut bytes = vec![0; width * height * channels * 4];
+        reader.read_exact(&mut bytes)?;
 
-        state
+        let floats = bytes.chunks_exact(4).map(|bytes|
+        {
+            f32::from_le_bytes(bytes.try_into().unwrap())
+        }).collect();
+
+        Ok((Point2{x: width, y: height}, floats))
     }
 
-    fn neighbor(self, temperature: f32) -> Self
+    fn write_lab<W: Write>(writer: &mut W, image: &LabImage) -> io::Result<()>
     {
-        let change = |v, scale|
-        {
-            UsefulOps::float_changed(v, temperature * scale)
-        };
+        let floats = image.pixels().flat_map(|pixel| [pixel.l, pixel.a, pixel.b]);
 
-        Self(self.0.map(|value| change(value, 0.01).clamp(-1.0, 1.0)))
+        Self::write_floats(writer, image.size_point(), floats)
     }
-}
 
-#[derive(Clone)]
-struct AngleParam(Option<f32>);
+    fn read_lab<R: Read>(reader: &mut R) -> io::Result<LabImage>
+    {
+        let (size, floats) = Self::read_floats(reader, 3)?;
 
-impl AngleParam
-{
-    fn random(allow: bool) -> Self
+        let pixels = floats.chunks_exact(3).map(|lab| Lab{l: lab[0], a: lab[1], b: lab[2]}).collect();
+
+        Ok(LabImage::from_raw(pixels, size.x, size.y))
+    }
+
+    fn write_gradient<W: Write>(writer: &mut W, image: &GradientImage) -> io::Result<()>
     {
-        Self(allow.then(||
-        {
-            fastrand::f32() * (2.0 * consts::PI)
-        }))
+        Self::write_floats(writer, image.size_point(), image.pixels().copied())
     }
-}
 
-impl Paramable for AngleParam
-{
-    fn apply(&self, mut state: ImageState) -> ImageState
+    fn read_gradient<R: Read>(reader: &mut R) -> io::Result<GradientImage>
     {
-        state.angle = Some(self.0.unwrap_or(0.0));
+        let (size, floats) = Self::read_floats(reader, 1)?;
 
-        state
+        Ok(GradientImage::from_raw(floats, size.x, size.y))
     }
 
-    fn neighbor(self, temperature: f32) -> Self
+    // only the params a warm start takes
+    fn write_info<W: Write>(writer: &mut W, info: &PlacementInfo) -> io::Result<()>
     {
-        let change = |v, scale|
+        let write_floats = |writer: &mut W, floats: &[f32]|
         {
-            UsefulOps::float_changed(v, temperature * scale)
+            floats.iter().try_for_each(|x| writer.write_all(&x.to_le_bytes()))
         };
 
-        Self(self.0.map(|value| change(value, 0.01) % (2.0 * consts::PI)))
-    }
-}
-
-#[derive(Clone)]
-struct PositionParam(Point2<f32>);
+        Self::write_option(writer, info.scale.as_ref(), |writer, scale|
+        {
+            write_floats(writer, &[scale.x, scale.y])
+        })?;
+        Self::write_option(writer, info.hue.as_ref(), |writer, hue|
+        {
+            write_floats(writer, &[hue.l, hue.a, hue.b])
+        })?;
+        Self::write_option(writer, info.transparency.as_ref(), |writer, x| write_floats(writer, &[*x]))?;
+        Self::write_option(writer, info.angle.as_ref(), |writer, x| write_floats(writer, &[*x]))?;
+        Self::write_option(writer, info.blend.as_ref(), |writer, mode|
+        {
+            let mode = match mode
+            {
+                BlendMode::Normal => 0_u8,
+                BlendMode::Dodge => 1,
+                BlendMode::Burn => 2
+            };
 
-impl PositionParam
-{
-    fn random() -> Self
-    {
-        Self(Point2{
-            x: fastrand::f32(),
-            y: fastrand::f32()
+            writer.write_all(&[mode])
         })
     }
-}
 
-impl Paramable for PositionParam
-{
-    fn apply(&self, mut state: ImageState) -> ImageState
+    fn read_info<R: Read>(reader: &mut R) -> io::Result<PlacementInfo>
     {
-        let add_image = state.add_image.take().unwrap();
+        let read_floats = |reader: &mut R, amount: usize|
+        {
+            (0..amount).map(|_| Self::read_u32(reader).map(f32::from_bits))
+                .collect::<io::Result<Vec<_>>>()
+        };
 
-        let size = state.image.size_point();
-        let position = (self.0 * size.map(|x| x as f32))
-            .zip(add_image.size_point()
-                 .zip(size)
-                 .map(|(small_size, total_size)| (total_size as i32 - small_size as i32).max(0)))
-            .map(|(x, limit)| (x as i32).clamp(0, limit));
+        let scale = Self::read_option(reader, |reader|
+        {
+            read_floats(reader, 2).map(|x| Point2{x: x[0], y: x[1]})
+        })?;
+        let hue = Self::read_option(reader, |reader|
+        {
+            read_floats(reader, 3).map(|x| Lab{l: x[0], a: x[1], b: x[2]})
+        })?;
+        let transparency = Self::read_option(reader, |reader| read_floats(reader, 1).map(|x| x[0]))?;
+        let angle = Self::read_option(reader, |reader| read_floats(reader, 1).map(|x| x[0]))?;
+        let blend = Self::read_option(reader, |reader|
+        {
+            let mut mode = [0];
+            reader.read_exact(&mut mode)?;
 
-        state.image = state.image.overlay_rotated(&add_image, position, state.angle.unwrap());
+            match mode[0]
+            {
+                0 => Ok(BlendMode::Normal),
+                1 => Ok(BlendMode::Dodge),
+                2 => Ok(BlendMode::Burn),
+                x => Err(Self::invalid(format!("unknown blend mode {x}")))
+            }
+        })?;
 
-        state
+        Ok(PlacementInfo{scale, hue, transparency, angle, blend, ..Default::default()})
     }
 
-    fn neighbor(self, temperature: f32) -> Self
+    fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32>
     {
-        let change = |v, scale|
-        {
-            UsefulOps::float_changed(v, temperature * scale)
-        };
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
 
-        Self(self.0.map(|x|
-        {
-            change(x, 1.0)
-        }))
+        Ok(u32::from_le_bytes(bytes))
     }
 }
 
-#[derive(Clone)]
-struct ImageAnnealable<'a, N>
+// lower resolution copy of the target that energies get measured against
+struct Downscaled
 {
-    original: &'a LabImage,
-    current: &'a LabImage,
-    node: N
+    factor: usize,
+    // shifts the block grid so it lines up with the margin
+    shift: usize,
+    image: LabImage,
+    gradient: Option<GradientImage>
 }
 
-impl<'a, N> ImageAnnealable<'a, N>
+// how many placements changed each pixel of the canvas (and of the mirror)
+struct Coverage
 {
-    pub fn new(
-        original: &'a LabImage,
-        current: &'a LabImage,
-        node: N
-    ) -> Self
-    where
-        N: Clone
-    {
-        Self{original, current, node}
-    }
+    full: GradientImage,
+    mirror: Option<GradientImage>
+}
 
-    pub fn applied(&self) -> LabImage
-    where
-        N: NodeTrait
+// counts placements in a coarse grid over the input so no part of it gets all of them
+struct PlacementGrid
+{
+    cells: Point2<i32>,
+    size: Point2<i32>,
+    cap: u32,
+    counts: Vec<u32>
+}
+
+impl PlacementGrid
+{
+    fn new(cells: u32, size: Point2<i32>, cap: u32) -> Self
     {
-        let state = ImageState{
-            image: self.current.clone(),
-            add_image: None,
-            angle: None
-        };
+        let cells = Point2::repeat(cells.max(1) as i32);
 
-        self.node.applies(state).image
+        Self{cells, size, cap, counts: vec![0; (cells.x * cells.y) as usize]}
     }
-}
 
-impl<'a, N> Annealable for ImageAnnealable<'a, N>
-where
-    N: NodeTrait + Clone
-{
-    fn random_neighbor(&self, temperature: f32) -> Self
+    // position is in input pixels
+    fn cell(&self, position: Point2<i32>) -> usize
     {
-        let mut output = self.clone();
+        let cell = (position * self.cells).zip(self.size).zip(self.cells).map(|((x, size), cells)|
+        {
+            (x / size.max(1)).clamp(0, cells - 1)
+        });
 
-        output.node = output.node.neighbors(temperature);
+        (cell.y * self.cells.x + cell.x) as usize
+    }
 
-        output
+    fn is_full(&self, position: Point2<i32>) -> bool
+    {
+        self.counts[self.cell(position)] >= self.cap
     }
 
-    fn energy(&self) -> f32
+    fn add(&mut self, position: Point2<i32>)
     {
-        let pixels = self.applied();
+        let cell = self.cell(position);
 
-        UsefulOps::image_difference(
-            self.original.pixels().copied(),
-            pixels.pixels().copied()
-        )
+        self.counts[cell] += 1;
     }
 }
 
-#[derive(Clone)]
-struct BackgroundAnnealable<'a>
+// how much of each pixel the placements cover, for cutting the collage out
+struct Cutout
 {
-    original: &'a LabImage,
-    color: Lab
+    // only l is used, overlaying works on colors
+    alpha: LabImage,
+    // what the placements went on top of, it gets taken back out of partly covered pixels
+    background: LabImage
 }
 
-impl<'a> Debug for BackgroundAnnealable<'a>
+// everything that gets kept up to date along with the canvas
+#[derive(Default)]
+struct Tracking
 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
-    {
-        f.debug_struct("BackgroundAnnealable")
-            .field("color", &self.color)
-            .finish()
-    }
+    mirror: Option<LabImage>,
+    coverage: Option<Coverage>,
+    grid: Option<PlacementGrid>,
+    cutout: Option<Cutout>,
+    // the last placement, new ones start from its params
+    warm: Option<PlacementInfo>
 }
 
-impl<'a> BackgroundAnnealable<'a>
+pub struct Collager
 {
-    pub fn new(original: &'a LabImage) -> Self
-    {
-        Self{original, color: Lab::random()}
-    }
+    config: CollagerConfig,
+    image: LabImage,
+    average: Lab,
+    gradient: Option<GradientImage>,
+    segments: Option<Segments>,
+    downscaled: Option<Downscaled>
+}
 
-    pub fn applied(&self) -> LabImage
+impl Collager
+{
+    pub fn new(config: CollagerConfig, image: Rgb32FImage) -> Self
     {
-        LabImage::repeat(
-            self.color,
-            self.original.width(),
-            self.original.height()
-        )
+        let image = LabImage::from_rgb_in(&image, config.conversion);
+
+        Self::from_lab(config, image)
     }
-}
 
-impl<'a> Annealable for BackgroundAnnealable<'a>
-{
-    fn random_neighbor(&self, temperature: f32) -> Self
+    fn from_lab(config: CollagerConfig, image: LabImage) -> Self
     {
-        let change = |v|
+        let average = image.average();
+
+        let gradient = (config.gradient_weight != 0.0).then(||
         {
-            UsefulOps::float_changed(v, temperature)
-        };
+            image.gradient_magnitude()
+        });
 
-        let c = self.color;
+        let downscaled = (config.energy_downscale > 1).then(||
+        {
+            let factor = config.energy_downscale as usize;
+            let shift = (factor - config.margin as usize % factor) % factor;
 
-        let mut output = self.clone();
+            let image = image.box_downscaled(factor, 0);
+            let gradient = (config.gradient_weight != 0.0).then(|| image.gradient_magnitude());
 
-        output.color = Lab{l: change(c.l), a: change(c.a), b: change(c.b)};
+            Downscaled{factor, shift, image, gradient}
+        });
 
-        output
+        let segments = (config.segment_weight != 0.0).then(||
+        {
+            let segments = Segments::new(&image, config.segment_step.max(f32::EPSILON));
+
+            eprintln!("segmented the input into {} regions", segments.len());
+
+            segments
+        });
+
+        Self{config, image, average, gradient, segments, downscaled}
     }
 
-    fn energy(&self) -> f32
+    pub fn average_color(&self) -> Lab
     {
-        let pixels = self.applied();
+        self.average
+    }
 
-        UsefulOps::image_difference(
-            self.original.pixels().copied(),
-            pixels.pixels().copied()
-        )
+    pub fn collage(&self, images: &dyn ImageSource, resume: Option<Resume>) -> Rendered
+    {
+        self.collage_with_progress(images, resume, Self::print_progress(self.amount()))
     }
-}
 
-pub trait Annealable
-{
-    fn random_neighbor(&self, temperature: f32) -> Self;
-    fn energy(&self) -> f32;
-}
+    pub fn collage_with_progress<F>(
+        &self,
+        images: &dyn ImageSource,
+        resume: Option<Resume>,
+        on_progress: F
+    ) -> Rendered
+    where
+        F: FnMut(Progress)
+    {
+        let (output, cutout) = match (self.config.tile_size, resume)
+        {
+            (Some(tile_size), None) =>
+            {
+                if self.config.checkpoint.is_some()
+                {
+                    eprintln!("checkpoints arent saved with tiles");
+                }
 
-#[derive(Debug, Clone)]
-pub struct StateEnergy<S>
-{
-    pub state: S,
-    pub energy: f32
-}
+                if self.config.die_cut
+                {
+                    eprintln!("tiles cant be die cut, the output stays opaque");
+                }
 
-impl<S: Annealable> StateEnergy<S>
-{
-    fn new(state: S) -> Self
-    {
-        let energy = state.energy();
+                if self.config.energy_report
+                {
+                    eprintln!("energy reports arent made with tiles");
+                }
 
-        Self{state, energy}
+                (self.collage_tiled(images, tile_size, on_progress), None)
+            },
+            (tile_size, resume) =>
+            {
+                if tile_size.is_some()
+                {
+                    eprintln!("tiles r ignored when resuming");
+                }
+
+                self.collage_canvas(images, resume, self.amount(), |_| None, on_progress)
+            }
+        };
+
+        self.finished(output, cutout)
     }
-}
 
-#[derive(Clone)]
-struct Annealer<S>
-{
-    state: StateEnergy<S>,
-    best_neighbor: Option<StateEnergy<S>>,
-    max_temperature: f32
-}
+    pub fn amount(&self) -> u32
+    {
+        self.config.auto_amount.map(|per_megapixel|
+        {
+            let megapixels = (self.image.width() * self.image.height()) as f32 / 1_000_000.0;
 
-impl<S: Annealable + Clone> Annealer<S>
-{
-    pub fn new(start: S, max_temperature: f32) -> Self
+            ((megapixels * per_megapixel).round() as u32).max(1)
+        }).unwrap_or(self.config.amount)
+    }
+
+    // places every image exactly once
+    pub fn stamp(&self, images: &dyn ImageSource) -> Rendered
     {
-        Self{state: StateEnergy::new(start), best_neighbor: None, max_temperature}
+        self.stamp_with_progress(images, Self::print_progress(images.len() as u32))
     }
 
-    pub fn anneal(self, steps: u32) -> S
+    pub fn stamp_with_progress<F>(&self, images: &dyn ImageSource, on_progress: F) -> Rendered
+    where
+        F: FnMut(Progress)
     {
-        self.anneal_with_energy(steps).state
+        let amount = images.len() as u32;
+
+        let (output, cutout) = self.collage_canvas(images, None, amount, |i| Some(i as usize), on_progress);
+
+        self.finished(output, cutout)
     }
 
-    pub fn anneal_with_energy(mut self, steps: u32) -> StateEnergy<S>
+    // fills the cells around jittered seed points with the closest colored little image
+    pub fn voronoi(&self, images: &dyn ImageSource, cells: u32, jitter: f32) -> Rendered
     {
-        for k in 0..steps
+        let size = self.image.size_point();
+        let mut rng = self.rng();
+
+        // one seed per space of a roughly square grid
+        let aspect = size.x as f32 / size.y as f32;
+        let columns = ((cells as f32 * aspect).sqrt().round() as usize).max(1);
+        let rows = (cells as usize).div_ceil(columns).max(1);
+
+        let spacing = size.map(|x| x as f32) / Point2{x: columns as f32, y: rows as f32};
+
+        let seeds: Vec<Point2<f32>> = (0..rows).flat_map(|y|
         {
-            let fraction = (k + 1) as f32 / steps as f32;
+            (0..columns).map(move |x| Point2{x, y})
+        }).map(|space|
+        {
+            let offset = Point2{x: rng.f32(), y: rng.f32()}.map(|x| 0.5 + (x - 0.5) * jitter);
+
+            (space.map(|x| x as f32) + offset) * spacing
+        }).collect();
+
+        let grid_size = Point2{x: columns as i32, y: rows as i32};
+
+        // seeds never leave their space so only the nearby spaces can have the closest one
+        let cells_image = GenericImage::from_fn(size.x, size.y, |position|
+        {
+            let point = position.map(|x| x as f32 + 0.5);
+            let space = (point / spacing).map(|x| x as i32);
+
+            let mut closest = (f32::INFINITY, 0);
+
+            for y in (space.y - 2).max(0)..(space.y + 3).min(grid_size.y)
+            {
+                for x in (space.x - 2).max(0)..(space.x + 3).min(grid_size.x)
+                {
+                    let index = y as usize * columns + x as usize;
+
+                    let difference = seeds[index] - point;
+                    let distance = difference.x * difference.x + difference.y * difference.y;
+
+                    if distance < closest.0
+                    {
+                        closest = (distance, index);
+                    }
+                }
+            }
+
+            closest.1
+        });
+
+        let mut bounds: Vec<Option<(Point2<i32>, Point2<i32>)>> = vec![None; seeds.len()];
+        cells_image.pixels_positions().for_each(|(position, cell)|
+        {
+            bounds[*cell] = UsefulOps::bounds_union(bounds[*cell], (position, position + 1));
+        });
+
+        let averages: Vec<Lab> = (0..images.len()).map(|index|
+        {
+            let image = images.get(index);
+
+            let (sum, weight) = image.pixels().fold((Lab::zero(), 0.0), |(sum, weight), pixel|
+            {
+                (sum.add_scaled(pixel.no_alpha(), pixel.alpha), weight + pixel.alpha)
+            });
+
+            sum.map(|x| x / weight.max(f32::EPSILON))
+        }).collect();
+
+        let integral = self.image.integral();
+
+        let mut canvas = self.background(&mut rng);
+        let margin = self.margin();
+
+        for (cell, bounds) in bounds.into_iter().enumerate()
+        {
+            if self.stopped()
+            {
+                eprintln!("stopping early after {cell} cells");
+
+                break;
+            }
+
+            let Some((low, high)) = bounds else { continue };
+
+            // the mean of the bounding box is close enough to the mean of the cell
+            let Some(mean) = integral.region_mean(low, high) else { continue };
+
+            let closest = averages.iter().enumerate().min_by(|(_, a), (_, b)|
+            {
+                a.distance(mean).total_cmp(&b.distance(mean))
+            }).map(|(index, _)| index);
+
+            let Some(closest) = closest else { continue };
+
+            let tile_size = (high - low).map(|x| x as usize);
+            let tile = images.get(closest).resized(tile_size, self.config.scale_filter);
+
+            tile.pixels_positions().for_each(|(tile_position, pixel)|
+            {
+                let position = low + tile_position;
+
+                if cells_image[position] == cell
+                {
+                    let canvas_pixel = &mut canvas[position + margin];
 
-            self.improve(self.temperature(1.0 - fraction));
+                    *canvas_pixel = canvas_pixel.blend(*pixel);
+                }
+            });
         }
 
-        self.best_neighbor.expect("steps must be above 0")
+        self.finished(canvas, None)
     }
 
-    fn temperature(&self, fraction: f32) -> f32
+    fn stopped(&self) -> bool
     {
-        self.max_temperature * fraction
+        self.config.stop.map(|stop| stop.load(Ordering::Relaxed)).unwrap_or(false)
     }
 
-    fn do_accept(&self, energy: f32, neighbor_energy: f32, temperature: f32) -> bool
+    fn print_progress(amount: u32) -> impl FnMut(Progress)
     {
-        let energy_delta = neighbor_energy - energy;
+        let tenth = (amount / 10).max(1);
+        let mut printed = 0;
+
+        move |progress|
+        {
+            // tiles report progress in jumps so this cant just check for multiples
+            if progress.placed / tenth > printed
+            {
+                printed = progress.placed / tenth;
 
-        energy_delta <= temperature
+                let percentage = progress.placed as f32 / progress.amount as f32 * 100.0;
+
+                eprintln!(
+                    "progress: {percentage:.1}% (ETA {}, energy {:.1}, {})",
+                    UsefulOps::duration_text(progress.eta()),
+                    progress.energy,
+                    progress.stats
+                );
+            }
+        }
     }
 
-    fn improve(&mut self, temperature: f32)
+    fn collage_canvas<I, F>(
+        &self,
+        images: &dyn ImageSource,
+        resume: Option<Resume>,
+        amount: u32,
+        index: I,
+        mut on_progress: F
+    ) -> (LabImage, Option<Cutout>)
+    where
+        I: Fn(u32) -> Option<usize>,
+        F: FnMut(Progress)
     {
-        let neighbor = StateEnergy::new(self.state.state.random_neighbor(temperature));
+        let Checkpoint{placed: start, seed, canvas: mut output, mut tracking} = match resume
+        {
+            Some(Resume::Checkpoint(checkpoint)) => *checkpoint,
+            Some(Resume::Image{image, placed}) => self.fresh_checkpoint(image, placed, &mut self.rng()),
+            None =>
+            {
+                let mut rng = self.rng();
+                let background = self.background(&mut rng);
 
-        let new_best = self.best_neighbor.is_none()
-            || (neighbor.energy < self.best_neighbor.as_ref().unwrap().energy);
+                self.fresh_checkpoint(background, 0, &mut rng)
+            }
+        };
 
-        if new_best
+        let mut rng = Rng::with_seed(seed);
+
+        let started = Instant::now();
+
+        let mut contributions = Vec::new();
+
+        for i in start..amount
         {
-            self.best_neighbor = Some(neighbor.clone());
+            if self.stopped()
+            {
+                eprintln!("stopping early after {i} images");
+
+                break;
+            }
+
+            let fraction = i as f32 / amount as f32;
+            let placement = self.best_placement(
+                images,
+                &output,
+                &tracking,
+                fraction,
+                index(i),
+                &mut rng
+            );
+
+            // every candidate was rejected (like being too small), so nothing gets placed
+            if placement.energy == f64::INFINITY
+            {
+                self.checkpointed(&mut output, &mut tracking, i + 1, &rng);
+
+                continue;
+            }
+
+            if let (Some(grid), Some(touched)) = (tracking.grid.as_mut(), placement.touched)
+            {
+                grid.add(UsefulOps::input_middle(touched, self.margin(), 1.0));
+            }
+
+            if let (Some(coverage), Some((low, high))) = (tracking.coverage.as_mut(), placement.touched)
+            {
+                self.update_coverage(coverage, &output, &placement.image, low, high);
+            }
+
+            if let (Some(mirror), Some(downscaled), Some((low, high))) =
+                (tracking.mirror.as_mut(), self.downscaled.as_ref(), placement.touched)
+            {
+                mirror.update_box_downscaled(
+                    &placement.image,
+                    downscaled.factor,
+                    downscaled.shift,
+                    low,
+                    high
+                );
+            }
+
+            if let (Some(cutout), Some(alpha)) = (tracking.cutout.as_mut(), placement.alpha)
+            {
+                cutout.alpha = alpha;
+            }
+
+            if let (true, Some((low, high))) = (self.config.energy_report, placement.touched)
+            {
+                let error = |canvas| self.region_error(canvas, low, high);
+
+                contributions.push(Contribution{
+                    placed: i,
+                    index: placement.info.index,
+                    reduction: error(&output) - error(&placement.image)
+                });
+            }
+
+            if self.config.warm_start
+            {
+                tracking.warm = Some(placement.info);
+            }
+
+            let energy = placement.energy;
+            let (touched, corners) = (placement.touched, placement.corners);
+            output = placement.image;
+
+            self.checkpointed(&mut output, &mut tracking, i + 1, &rng);
+
+            on_progress(Progress{
+                placed: i + 1,
+                average_time: started.elapsed() / (i + 1 - start),
+                amount,
+                energy,
+                stats: placement.stats,
+                info: Some(placement.info)
+            });
+
+            if self.config.debug && (i + 1) % self.config.debug_every.max(1) == 0
+            {
+                let debug_dir = PathBuf::from("test");
+
+                if !debug_dir.exists()
+                {
+                    fs::create_dir(&debug_dir).unwrap();
+                }
+
+                let mut debug_image = output.clone();
+                if self.config.debug_boxes
+                {
+                    Self::draw_debug_boxes(&mut debug_image, touched, corners);
+                }
+
+                let image_name = format!("image{i}.png");
+                self.to_rgb(debug_image).save(debug_dir.join(image_name)).unwrap();
+            }
         }
 
-        if self.do_accept(self.state.energy, neighbor.energy, temperature)
+        if self.config.energy_report
         {
-            self.state = neighbor;
+            Self::print_energy_report(&contributions, images.len());
+        }
+
+        (output, tracking.cutout)
+    }
+
+    // touched bounds in magenta and the rotated outline in green
+    fn draw_debug_boxes(
+        image: &mut LabImage,
+        touched: Option<(Point2<i32>, Point2<i32>)>,
+        corners: Option<[Point2<f32>; 4]>
+    )
+    {
+        let mut draw_loop = |corners: [Point2<f32>; 4], color: Lab|
+        {
+            (0..4).for_each(|i|
+            {
+                image.draw_line(corners[i], corners[(i + 1) % 4], color);
+            });
+        };
+
+        if let Some((low, high)) = touched
+        {
+            let (low, high) = (low.map(|x| x as f32), (high - 1).map(|x| x as f32));
+
+            let corners = [low, Point2{x: high.x, y: low.y}, high, Point2{x: low.x, y: high.y}];
+            draw_loop(corners, Lab{l: 60.0, a: 98.0, b: -60.0});
         }
+
+        if let Some(corners) = corners
+        {
+            draw_loop(corners, Lab{l: 88.0, a: -80.0, b: 80.0});
+        }
+    }
+
+    // color error of the part of the canvas between low and high
+    fn region_error(&self, canvas: &LabImage, low: Point2<i32>, high: Point2<i32>) -> f64
+    {
+        let margin = self.margin();
+
+        let low = (low - margin).zip(Point2::repeat(0)).map(|(a, b)| a.max(b));
+        let high = (high - margin).zip(self.image.size_point()).map(|(a, b)| a.min(b as i32));
+
+        let size = (high - low).map(|x| x.max(0) as usize);
+
+        UsefulOps::image_difference_at(
+            &self.image.cropped(low, size),
+            canvas,
+            low + margin,
+            self.config.distance_exponent,
+            1
+        )
+    }
+
+    fn print_energy_report(contributions: &[Contribution], sources: usize)
+    {
+        if contributions.is_empty()
+        {
+            return;
+        }
+
+        let total: f64 = contributions.iter().map(|x| x.reduction).sum();
+        let average = total / contributions.len() as f64;
+
+        eprintln!("energy report: {} placements lowered the error by {total:.1}", contributions.len());
+        eprintln!("average {average:.1} per placement");
+
+        let mut sorted: Vec<&Contribution> = contributions.iter().collect();
+        sorted.sort_by(|a, b| b.reduction.total_cmp(&a.reduction));
+
+        let shown = 5.min(sorted.len());
+
+        eprintln!("most useful:");
+        sorted[..shown].iter().for_each(|x| eprintln!("  {x}"));
+
+        eprintln!("least useful:");
+        sorted[sorted.len() - shown..].iter().for_each(|x| eprintln!("  {x}"));
+
+        // barely changed anything, removing them wouldnt be noticed much
+        let useless = contributions.iter().filter(|x| x.reduction < average * 0.1).count();
+        eprintln!("{useless} placements did less than a tenth of the average");
+
+        let mut per_image: HashMap<usize, (u32, f64)> = HashMap::new();
+        contributions.iter().filter_map(|x| x.index.map(|index| (index, x.reduction)))
+            .for_each(|(index, reduction)|
+            {
+                let entry = per_image.entry(index).or_default();
+
+                entry.0 += 1;
+                entry.1 += reduction;
+            });
+
+        let unused = sources.saturating_sub(per_image.len());
+        if unused > 0
+        {
+            eprintln!("{unused} images were never placed");
+        }
+
+        let mut per_image: Vec<_> = per_image.into_iter().collect();
+        per_image.sort_by(|a, b| b.1.1.total_cmp(&a.1.1).then(a.0.cmp(&b.0)));
+
+        eprintln!("per image:");
+        per_image.into_iter().for_each(|(index, (count, reduction))|
+        {
+            eprintln!("  image {index}: placed {count} times, lowered the error by {reduction:.1}");
+        });
+    }
+
+    // anneals overlapping parts of the target separately and blends them together
+    fn collage_tiled<F>(
+        &self,
+        images: &dyn ImageSource,
+        tile_size: u32,
+        mut on_progress: F
+    ) -> LabImage
+    where
+        F: FnMut(Progress)
+    {
+        let size = self.image.size_point().map(|x| x as i32);
+        let tile_size = tile_size.max(1) as i32;
+        let overlap = self.config.tile_overlap as i32;
+
+        let tiles_amount = size.map(|x| (x as u32).div_ceil(tile_size as u32) as i32);
+        let tiles: Vec<(Point2<i32>, Point2<i32>)> = (0..tiles_amount.y).flat_map(|y|
+        {
+            (0..tiles_amount.x).map(move |x| Point2{x, y})
+        }).map(|tile|
+        {
+            let low = tile * tile_size;
+            let high = (low + tile_size).zip(size).map(|(a, b)| a.min(b));
+
+            let low = low.map(|x| (x - overlap).max(0));
+            let high = (high + overlap).zip(size).map(|(a, b)| a.min(b));
+
+            (low, high)
+        }).collect();
+
+        let total_area = (size.x * size.y) as f32;
+
+        // the outer threads already keep every core busy
+        let inner_threads = (self.threads() / tiles.len()).max(1);
+
+        let parts = self.parallel_map(tiles.len() as u32, |i|
+        {
+            let (low, high) = tiles[i as usize];
+            let tile_size = high - low;
+
+            let area = (tile_size.x * tile_size.y) as f32;
+            let amount = ((self.amount() as f32 * area / total_area).round() as u32).max(1);
+
+            let config = CollagerConfig{
+                amount,
+                auto_amount: None,
+                tile_size: None,
+                threads: Some(inner_threads),
+                seed: self.config.seed.map(|seed| seed.wrapping_add(i as u64)),
+                checkpoint: None,
+                die_cut: false,
+                energy_report: false,
+                debug: false,
+                ..self.config.clone()
+            };
+
+            let target = self.image.cropped(low, tile_size.map(|x| x as usize));
+            let collager = Collager::from_lab(config, target);
+
+            let (canvas, _) = collager.collage_canvas(images, None, amount, |_| None, |_| {});
+
+            let energy = UsefulOps::image_difference_at(
+                &collager.image,
+                &canvas,
+                collager.margin(),
+                self.config.distance_exponent,
+                1
+            );
+
+            (low, canvas, amount, energy)
+        });
+
+        let canvas_size = self.canvas_size();
+        let canvas_end = canvas_size.map(|x| x as i32);
+
+        let mut sum = LabImage::repeat(Lab::zero(), canvas_size.x, canvas_size.y);
+        let mut weights = GradientImage::repeat(0.0, canvas_size.x, canvas_size.y);
+
+        // tiles fade out over the whole shared area so the seams get blended
+        let ramp = (2 * (overlap + self.config.margin as i32)).max(1) as f32;
+
+        let total_amount = parts.iter().map(|(_, _, amount, _)| amount).sum();
+        let mut placed = 0;
+
+        parts.into_iter().for_each(|(low, canvas, amount, energy)|
+        {
+            let tile_end = canvas.size_point().map(|x| x as i32);
+
+            let fade = |position: i32, start: i32, end: i32, canvas_end: i32|
+            {
+                let from_start = if start > 0 { position } else { i32::MAX };
+                let from_end = if start + end < canvas_end { end - 1 - position } else { i32::MAX };
+
+                ((from_start.min(from_end) as f32 + 1.0) / ramp).min(1.0)
+            };
+
+            canvas.pixels_positions().for_each(|(position, pixel)|
+            {
+                let weight = fade(position.x, low.x, tile_end.x, canvas_end.x)
+                    * fade(position.y, low.y, tile_end.y, canvas_end.y);
+
+                let global = low + position;
+
+                sum[global] = sum[global].add_scaled(*pixel, weight);
+                weights[global] += weight;
+            });
+
+            placed += amount;
+
+            on_progress(Progress{
+                placed,
+                // tiles only report once theyre all done, so nothing is left
+                average_time: Duration::ZERO,
+                amount: total_amount,
+                energy,
+                stats: AnnealStats::default(),
+                info: None
+            });
+        });
+
+        sum.pixels_positions_mut().for_each(|(position, pixel)|
+        {
+            let weight = weights[position];
+
+            *pixel = pixel.map(|x| x / weight);
+        });
+
+        sum
+    }
+
+    fn finished(&self, output: LabImage, cutout: Option<Cutout>) -> Rendered
+    {
+        let final_error = UsefulOps::image_difference_at(
+            &self.image,
+            &output,
+            self.margin(),
+            self.config.distance_exponent,
+            1
+        );
+
+        let error_per_pixel = final_error / (self.image.width() * self.image.height()) as f64;
+
+        eprintln!("final error per pixel: {error_per_pixel:.3}");
+
+        if self.config.stats
+        {
+            self.print_error_stats(&output);
+        }
+
+        let Some(Cutout{alpha, background}) = cutout else
+        {
+            return self.rendered(output.vignetted(self.config.vignette));
+        };
+
+        let alpha = alpha.map(|pixel| pixel.l.clamp(0.0, 1.0));
+
+        // partly covered pixels still have some of the background mixed into them
+        let output = LabImage::from_fn(output.width(), output.height(), |position|
+        {
+            let covered = alpha[position];
+
+            if covered > 0.0
+            {
+                output[position].add_scaled(background[position], covered - 1.0).map(|x| x / covered)
+            } else
+            {
+                output[position]
+            }
+        });
+
+        Rendered::new(output.vignetted(self.config.vignette), Some(alpha), self.config.conversion, self.threads())
+    }
+
+    fn print_error_stats(&self, output: &LabImage)
+    {
+        let margin = self.margin();
+        let high = margin + self.image.size_point().map(|x| x as i32);
+
+        let mut errors: Vec<f32> = self.image.pixels().zip(output.pixels_between(margin, high))
+            .map(|(original, (_, changed))|
+            {
+                UsefulOps::distance_error(original.distance(*changed), self.config.distance_exponent)
+            }).collect();
+
+        if errors.is_empty()
+        {
+            return;
+        }
+
+        let mut percentile = |fraction: f32|
+        {
+            let index = ((errors.len() - 1) as f32 * fraction).round() as usize;
+
+            *errors.select_nth_unstable_by(index, f32::total_cmp).1
+        };
+
+        let median = percentile(0.5);
+        let high = percentile(0.95);
+
+        eprintln!("median error per pixel: {median:.3}, 95th percentile: {high:.3}");
+    }
+
+    pub fn rendered(&self, image: LabImage) -> Rendered
+    {
+        Rendered::new(image, None, self.config.conversion, self.threads())
+    }
+
+    pub fn to_rgb(&self, image: LabImage) -> RgbImage
+    {
+        image.to_rgb_in(self.config.conversion, self.threads())
+    }
+
+    pub fn best_single_placement(&self, images: &dyn ImageSource) -> (LabImage, PlacementInfo)
+    {
+        let mut rng = self.rng();
+        let background = self.background(&mut rng);
+
+        let tracking = Tracking{mirror: self.mirror(&background), ..Default::default()};
+        let placement = self.best_placement(images, &background, &tracking, 0.0, None, &mut rng);
+
+        (placement.image, placement.info)
+    }
+
+    pub fn canvas_size(&self) -> Point2<usize>
+    {
+        self.image.size_point().map(|x| x + self.config.margin as usize * 2)
+    }
+
+    pub fn save_checkpoint(&self, checkpoint: &Checkpoint, path: &Path) -> io::Result<()>
+    {
+        // a stop halfway through writing shouldnt destroy the last good checkpoint
+        let temporary = path.with_extension("tmp");
+
+        checkpoint.write_to(BufWriter::new(fs::File::create(&temporary)?))?;
+
+        fs::rename(temporary, path)
+    }
+
+    pub fn load_checkpoint(&self, path: &Path) -> io::Result<Checkpoint>
+    {
+        let reader = BufReader::new(fs::File::open(path)?);
+        let checkpoint = Checkpoint::read_from(reader, self.placement_grid())?;
+
+        let mismatch = |name: &str|
+        {
+            Checkpoint::invalid(format!("checkpoint has a different {name} than this config"))
+        };
+
+        if checkpoint.canvas.size_point() != self.canvas_size()
+        {
+            return Err(mismatch("canvas size"));
+        }
+
+        if checkpoint.tracking.mirror.is_some() != self.downscaled.is_some()
+        {
+            return Err(mismatch("energy downscale"));
+        }
+
+        if checkpoint.tracking.coverage.is_some() != (self.config.overlap_weight != 0.0)
+        {
+            return Err(mismatch("overlap weight"));
+        }
+
+        if checkpoint.tracking.cutout.is_some() != self.config.die_cut
+        {
+            return Err(mismatch("die cut"));
+        }
+
+        Ok(checkpoint)
+    }
+
+    fn rng(&self) -> Rng
+    {
+        self.config.seed.map(Rng::with_seed).unwrap_or_default()
+    }
+
+    fn placement_grid(&self) -> Option<PlacementGrid>
+    {
+        self.config.grid_cap.map(|cap|
+        {
+            PlacementGrid::new(self.config.grid_cells, self.image.size_point().map(|x| x as i32), cap)
+        })
+    }
+
+    // the rng is only used for its seed, every placement draws its own seeds from it
+    fn fresh_checkpoint(&self, canvas: LabImage, placed: u32, rng: &mut Rng) -> Checkpoint
+    {
+        let mirror = self.mirror(&canvas);
+
+        // resumed canvases dont know their coverage so it starts from nothing
+        let coverage = (self.config.overlap_weight != 0.0).then(||
+        {
+            let size = canvas.size_point();
+            let full = GradientImage::repeat(0.0, size.x, size.y);
+
+            Coverage{mirror: self.mirror(&full), full}
+        });
+
+        let cutout = self.config.die_cut.then(||
+        {
+            let size = canvas.size_point();
+
+            Cutout{alpha: LabImage::repeat(Lab::zero(), size.x, size.y), background: canvas.clone()}
+        });
+
+        let tracking = Tracking{mirror, coverage, grid: self.placement_grid(), cutout, warm: None};
+
+        Checkpoint{placed, seed: rng.u64(..), canvas, tracking}
+    }
+
+    // saves the state after a placement if theres somewhere to save it
+    fn checkpointed(&self, canvas: &mut LabImage, tracking: &mut Tracking, placed: u32, rng: &Rng)
+    {
+        let Some(path) = self.config.checkpoint.as_ref() else { return };
+
+        let checkpoint = Checkpoint{
+            placed,
+            seed: rng.get_seed(),
+            canvas: mem::replace(canvas, LabImage::repeat(Lab::zero(), 0, 0)),
+            tracking: mem::take(tracking)
+        };
+
+        if let Err(err) = self.save_checkpoint(&checkpoint, path)
+        {
+            eprintln!("cant save checkpoint to {}: {err}", path.display());
+        }
+
+        *canvas = checkpoint.canvas;
+        *tracking = checkpoint.tracking;
+    }
+
+    fn margin(&self) -> Point2<i32>
+    {
+        Point2::repeat(self.config.margin as i32)
+    }
+
+    fn update_coverage(
+        &self,
+        coverage: &mut Coverage,
+        before: &LabImage,
+        after: &LabImage,
+        low: Point2<i32>,
+        high: Point2<i32>
+    )
+    {
+        for y in low.y..high.y
+        {
+            for x in low.x..high.x
+            {
+                let position = Point2{x, y};
+
+                if before[position].distance(after[position]) > 0.0
+                {
+                    coverage.full[position] += 1.0;
+                }
+            }
+        }
+
+        if let (Some(mirror), Some(downscaled)) = (coverage.mirror.as_mut(), self.downscaled.as_ref())
+        {
+            mirror.update_box_downscaled(&coverage.full, downscaled.factor, downscaled.shift, low, high);
+        }
+    }
+
+    // downscaled copy of the canvas that gets updated along with it
+    fn mirror<T: Resamplable>(&self, canvas: &GenericImage<T>) -> Option<GenericImage<T>>
+    {
+        self.downscaled.as_ref().map(|downscaled|
+        {
+            canvas.box_downscaled(downscaled.factor, downscaled.shift)
+        })
+    }
+
+    fn background(&self, rng: &mut Rng) -> LabImage
+    {
+        let flat_color = self.config.background_color.or_else(||
+        {
+            self.config.flat_background.then_some(self.average)
+        });
+
+        let size = self.canvas_size();
+
+        if let Some(color) = flat_color
+        {
+            return LabImage::repeat(color, size.x, size.y);
+        }
+
+        let start = match self.config.background_init
+        {
+            BackgroundInit::Random => Lab::random(rng),
+            BackgroundInit::Average => self.average,
+            BackgroundInit::Dominant =>
+            {
+                // no need to look at every pixel of a huge image
+                let step = (self.image.pixels().len() / 10_000).max(1);
+                let pixels: Vec<_> = self.image.pixels().step_by(step).copied().collect();
+
+                colors::dominant_color(&pixels, 5).unwrap_or(self.average)
+            }
+        };
+
+        match self.config.background
+        {
+            Background::Solid =>
+            {
+                let background = BackgroundAnnealable::new(
+                    &self.image,
+                    start,
+                    self.config.distance_exponent
+                );
+
+                let (best, stats) = self.background_optimized(background, rng);
+
+                eprintln!("background {stats}");
+
+                LabImage::repeat(best.state.color, size.x, size.y)
+            },
+            Background::Gradient =>
+            {
+                let background = GradientBackgroundAnnealable::new(
+                    &self.image,
+                    start,
+                    self.config.distance_exponent
+                );
+
+                let (best, stats) = self.background_optimized(background, rng);
+
+                eprintln!("background {stats}");
+
+                best.state.rendered(size, self.margin())
+            },
+            Background::Blocks =>
+            {
+                let background = BlockBackgroundAnnealable::new(
+                    &self.image,
+                    start,
+                    self.config.background_cells,
+                    self.config.distance_exponent
+                );
+
+                let (best, stats) = self.background_optimized(background, rng);
+
+                eprintln!("background {stats}");
+
+                best.state.rendered(size, self.margin())
+            }
+        }
+    }
+
+    // background anneals r cheap, so the best of a few independent ones is kept
+    fn background_optimized<S>(&self, background: S, rng: &mut Rng) -> (StateEnergy<S>, AnnealStats)
+    where
+        S: Annealable + Clone + Send + Sync
+    {
+        let seeds: Vec<u64> = (0..self.config.background_starts).map(|_| rng.u64(..)).collect();
+
+        let threads = self.batch_threads(self.config.background_starts);
+
+        let (bests, stats): (Vec<_>, Vec<_>) = self.parallel_map(self.config.background_starts, |i|
+        {
+            let rng = Rng::with_seed(seeds[i as usize]);
+
+            let start = StateEnergy::new(background.clone());
+
+            self.optimized(start, 30.0, self.config.background_steps, threads, rng)
+        }).into_iter().unzip();
+
+        let best = StateEnergy::best(bests).expect("background starts must be at least 1");
+        let stats = stats.into_iter().fold(AnnealStats::default(), AnnealStats::merged);
+
+        (best, stats)
+    }
+
+    fn best_placement(
+        &self,
+        images: &dyn ImageSource,
+        output: &LabImage,
+        tracking: &Tracking,
+        fraction: f32,
+        index: Option<usize>,
+        rng: &mut Rng
+    ) -> Placement
+    {
+        let unlocked = |allow: bool, unlock: f32|
+        {
+            allow && fraction >= unlock
+        };
+
+        let allow_scaling = unlocked(self.config.allow_scaling, self.config.scaling_unlock);
+        let allow_rotation = unlocked(self.config.allow_rotation, self.config.rotation_unlock);
+        let allow_hue = unlocked(self.config.allow_hue, self.config.hue_unlock);
+        let allow_transparency = unlocked(
+            self.config.allow_transparency,
+            self.config.transparency_unlock
+        );
+
+        let scale_decay = match self.config.scale_decay
+        {
+            ScaleDecay::Constant => 1.0,
+            ScaleDecay::Linear => 1.0 - fraction,
+            ScaleDecay::Exponential => (-5.0 * fraction).exp()
+        };
+
+        let scale_range = self.config.scale_max - self.config.scale_min;
+        let scale_ceiling = self.config.scale_min + scale_range * scale_decay;
+
+        // drawn in the order of the chain, the node is built the same way render_plan builds it
+        let params = |rng: &mut Rng|
+        {
+            let info = PlacementInfo{
+                index: Some(index.unwrap_or_else(|| IndexParam::random(images, rng))),
+                scale: ScaleParam::random(
+                    allow_scaling,
+                    !self.config.allow_nonuniform_scaling,
+                    self.config.scale_min..scale_ceiling,
+                    rng
+                ),
+                hue: HueParam::random(allow_hue, rng),
+                transparency: TransparencyParam::random(allow_transparency, rng),
+                angle: AngleParam::random(allow_rotation, self.config.angle_mode, self.config.angle_steps, rng),
+                blend: BlendParam::random(self.config.allow_blend_modes, rng),
+                position: Some(PositionParam::random(rng))
+            };
+
+            let node = self.placement_node(images, &info, index.is_some());
+
+            match tracking.warm.as_ref()
+            {
+                Some(warm) => node.warm_started(warm),
+                None => node
+            }
+        };
+
+        let gradient_energy = |(target, base)|
+        {
+            GradientEnergy{target, base, weight: self.config.gradient_weight}
+        };
+
+        let mirror = tracking.mirror.as_ref();
+        let downscaled_margin = self.downscaled.as_ref().map(|downscaled|
+        {
+            Point2::repeat(((self.config.margin as usize + downscaled.shift) / downscaled.factor) as i32)
+        });
+
+        let full_base = self.gradient.as_ref().map(|target|
+        {
+            GradientBase::new(target, output, self.margin())
+        });
+
+        let downscaled_base = self.downscaled.as_ref().zip(mirror).zip(downscaled_margin)
+            .and_then(|((downscaled, mirror), margin)|
+            {
+                downscaled.gradient.as_ref().map(|target| GradientBase::new(target, mirror, margin))
+            });
+
+        let full = EnergyTarget{
+            original: &self.image,
+            gradient: self.gradient.as_ref().zip(full_base.as_ref()).map(gradient_energy),
+            canvas: output,
+            coverage: tracking.coverage.as_ref().map(|coverage| &coverage.full),
+            segments: self.segments.as_ref(),
+            grid: tracking.grid.as_ref(),
+            margin: self.margin(),
+            downscale: 1.0
+        };
+
+        let downscaled = self.downscaled.as_ref().zip(mirror).zip(downscaled_margin)
+            .map(|((downscaled, mirror), margin)|
+            {
+                EnergyTarget{
+                    original: &downscaled.image,
+                    gradient: downscaled.gradient.as_ref().zip(downscaled_base.as_ref()).map(gradient_energy),
+                    canvas: mirror,
+                    coverage: tracking.coverage.as_ref().and_then(|coverage| coverage.mirror.as_ref()),
+                    segments: self.segments.as_ref(),
+                    grid: tracking.grid.as_ref(),
+                    margin,
+                    downscale: downscaled.factor as f32
+                }
+            });
+
+        let weights = self.energy_weights();
+
+        let seeds: Vec<u64> = (0..self.config.starts).map(|_| rng.u64(..)).collect();
+
+        let threads = self.batch_threads(self.config.starts);
+
+        let anneal = |seed: u64|
+        {
+            let mut rng = Rng::with_seed(seed);
+
+            let candidates = (0..self.config.greedy_candidates.max(1)).map(|_|
+            {
+                StateEnergy::new(ImageAnnealable::new(
+                    full,
+                    downscaled,
+                    weights,
+                    params(&mut rng)
+                ))
+            });
+
+            let start = StateEnergy::best(candidates).unwrap();
+
+            self.optimized(start, self.config.starting_temperature, self.config.steps, threads, rng)
+        };
+
+        let (bests, stats): (Vec<_>, Vec<_>) = self.parallel_map(self.config.starts, |i|
+        {
+            anneal(seeds[i as usize])
+        }).into_iter().unzip();
+
+        let best = StateEnergy::best(bests).expect("steps must be at least 1");
+        let stats = stats.into_iter().fold(AnnealStats::default(), AnnealStats::merged);
+
+        let state = best.state.applied();
+        let alpha = tracking.cutout.as_ref().map(|cutout| best.state.covered(&cutout.alpha));
+
+        let mut info = best.state.node.placement_info(PlacementInfo::default());
+        if info.index.is_some_and(|index| !images.rotatable(index))
+        {
+            info.angle = None;
+        }
+
+        Placement{
+            image: state.image,
+            energy: best.energy,
+            stats,
+            info,
+            touched: state.touched,
+            corners: state.corners,
+            alpha
+        }
+    }
+
+    fn energy_weights(&self) -> EnergyWeights
+    {
+        EnergyWeights{
+            color: self.config.color_weight,
+            exponent: self.config.distance_exponent,
+            ssim: self.config.ssim_weight,
+            segment: self.config.segment_weight,
+            overlap: self.config.overlap_weight,
+            stride: self.config.energy_stride.max(1)
+        }
+    }
+
+    // the params of a placement, annealing starts from these and render_plan rebuilds
+    // the annealed ones the same way, a fixed index never changes while annealing
+    fn placement_node<'a>(
+        &self,
+        images: &'a dyn ImageSource,
+        info: &PlacementInfo,
+        fixed: bool
+    ) -> impl NodeTrait + Clone + 'a
+    {
+        // angles come before scaling so pre rotated images can be looked up by their source
+        Node::cons(
+            IndexParam{images, index: info.index.expect("placement needs an index"), fixed},
+            Node::cons(
+                AngleParam{
+                    images,
+                    angle: info.angle,
+                    mode: self.config.angle_mode,
+                    steps: self.config.angle_steps,
+                    uniform: !self.config.allow_scaling || !self.config.allow_nonuniform_scaling,
+                    edge: self.config.edge_mode,
+                    sampling: self.config.rotation_sampling
+                },
+                Node::cons(
+                    ScaleParam{
+                        scale: info.scale,
+                        uniform: !self.config.allow_nonuniform_scaling,
+                        filter: self.config.scale_filter,
+                        min_pixels: self.config.min_placement_pixels
+                    },
+                    Node::cons(
+                        HueParam(info.hue),
+                        Node::cons(
+                            TransparencyParam{
+                                transparency: info.transparency,
+                                lower_bound: self.config.transparency_lower_bound,
+                                mode: self.config.transparency_mode
+                            }.constrained(),
+                            Node::cons(
+                                BlendParam{mode: info.blend, conversion: self.config.conversion},
+                                Node::cons(
+                                    PositionParam{
+                                        position: info.position.expect("placement needs a position"),
+                                        shadow: self.config.shadow,
+                                        stroke: self.config.stroke,
+                                        edge: self.config.edge_mode,
+                                        sampling: self.config.rotation_sampling,
+                                        min_alpha: self.config.min_alpha,
+                                        max_opacity: self.config.max_tile_opacity
+                                    },
+                                    Node::nil())))))))
+    }
+
+    // draws the placements in order on a flat background, exactly like collaging with
+    // them would, indices r into images and positions r fractions of the canvas
+    pub fn render_plan(
+        &self,
+        background: Lab,
+        placements: &[PlacementInfo],
+        images: &dyn ImageSource
+    ) -> LabImage
+    {
+        let size = self.canvas_size();
+
+        placements.iter().fold(LabImage::repeat(background, size.x, size.y), |canvas, info|
+        {
+            self.placement_node(images, info, true).applies(ImageState::new(canvas, 1.0)).image
+        })
+    }
+
+    // composite and full resolution energy of one exact placement, so the pipeline
+    // can be checked from outside without going through the annealer
+    #[cfg(feature = "testing")]
+    pub fn evaluate_placement(
+        &self,
+        images: &dyn ImageSource,
+        canvas: &LabImage,
+        info: &PlacementInfo
+    ) -> StateEnergy<LabImage>
+    {
+        let node = self.placement_node(images, info, true);
+
+        let base = self.gradient.as_ref().map(|target| GradientBase::new(target, canvas, self.margin()));
+
+        let target = EnergyTarget{
+            original: &self.image,
+            gradient: self.gradient.as_ref().zip(base.as_ref()).map(|(target, base)|
+            {
+                GradientEnergy{target, base, weight: self.config.gradient_weight}
+            }),
+            canvas,
+            coverage: None,
+            segments: self.segments.as_ref(),
+            grid: None,
+            margin: self.margin(),
+            downscale: 1.0
+        };
+
+        let weights = EnergyWeights{stride: 1, ..self.energy_weights()};
+        let annealable = ImageAnnealable::new(target, None, weights, node);
+
+        StateEnergy{energy: annealable.energy(), state: annealable.applied().image}
+    }
+
+    fn optimized<S>(
+        &self,
+        start: StateEnergy<S>,
+        max_temperature: f32,
+        steps: u32,
+        threads: usize,
+        rng: Rng
+    ) -> (StateEnergy<S>, AnnealStats)
+    where
+        S: Annealable + Clone + Send + Sync
+    {
+        match self.config.optimizer
+        {
+            Optimizer::Annealing =>
+            {
+                Annealer::from_state_energy(start, max_temperature)
+                    .with_temperature_floor(self.config.temperature_floor)
+                    .with_acceptance_gamma(self.config.acceptance_gamma)
+                    .with_strict_acceptance(self.config.strict_acceptance)
+                    .with_patience(self.config.patience, self.config.patience_epsilon as f64)
+                    .with_cycles(self.config.cooling_cycles)
+                    .with_tabu(self.config.tabu_size)
+                    .with_batch(self.config.neighbor_batch)
+                    .with_threads(threads)
+                    .with_commit(self.config.commit)
+                    .with_rng(rng)
+                    .anneal_with_stats(steps)
+            },
+            Optimizer::HillClimbing =>
+            {
+                HillClimber::from_state_energy(start, max_temperature)
+                    .with_temperature_floor(self.config.temperature_floor)
+                    .with_rng(rng)
+                    .climb_with_stats(steps)
+            }
+        }
+    }
+
+    fn threads(&self) -> usize
+    {
+        self.config.threads.unwrap_or_else(||
+        {
+            thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1)
+        }).max(1)
+    }
+
+    fn parallel_map<T, F>(&self, amount: u32, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(u32) -> T + Sync
+    {
+        parallel_map(self.threads(), amount, f)
+    }
+
+    // threads left for the neighbor batches of each of amount anneals running at once
+    fn batch_threads(&self, amount: u32) -> usize
+    {
+        // the anneals already keep the cores busy
+        if self.threads().min(amount as usize) > 1
+        {
+            1
+        } else
+        {
+            self.threads()
+        }
+    }
+}
+
+// results are always in the same order as the indices
+fn parallel_map<T, F>(threads: usize, amount: u32, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(u32) -> T + Sync
+{
+    let threads = threads.min(amount as usize);
+
+    if threads <= 1
+    {
+        return (0..amount).map(f).collect();
+    }
+
+    let chunk = (amount as usize).div_ceil(threads) as u32;
+
+    thread::scope(|scope|
+    {
+        let f = &f;
+
+        let handles: Vec<_> = (0..amount).step_by(chunk as usize).map(|start|
+        {
+            let end = (start + chunk).min(amount);
+
+            scope.spawn(move ||
+            {
+                (start..end).map(f).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+// if lisp is so good why havent they made lisp 2?
+#[derive(Clone)]
+struct Node<T, C>(T, C);
+
+impl<T, C> Node<T, C>
+{
+    pub fn cons(value: T, other: C) -> Self
+    {
+        Self(value, other)
+    }
+}
+
+impl Node<(), ()>
+{
+    pub fn nil() -> () {}
+}
+
+trait NodeTrait
+{
+    type Item;
+    type Child;
+
+    // the word applies makes no sense here but i dont wanna be confused
+    fn applies(&self, state: ImageState) -> ImageState;
+
+    // applies split in 2, positional params have to come after the rest for these to match
+    fn applies_tile(&self, state: ImageState) -> ImageState;
+    fn applies_position(&self, state: ImageState) -> ImageState;
+
+    fn neighbors(self, temperature: f32, rng: &mut Rng) -> Self;
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo;
+    fn warm_started(self, info: &PlacementInfo) -> Self;
+}
+
+impl NodeTrait for ()
+{
+    type Item = ();
+    type Child = ();
+
+    fn applies(&self, state: ImageState) -> ImageState {state}
+    fn applies_tile(&self, state: ImageState) -> ImageState {state}
+    fn applies_position(&self, state: ImageState) -> ImageState {state}
+    fn neighbors(self, _temperature: f32, _rng: &mut Rng) -> () {}
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo {info}
+    fn warm_started(self, _info: &PlacementInfo) -> () {}
+}
+
+impl<T: Paramable, C: NodeTrait> NodeTrait for Node<T, C>
+{
+    type Item = T;
+    type Child = C;
+
+    fn applies(&self, state: ImageState) -> ImageState
+    {
+        self.1.applies(self.0.apply(state))
+    }
+
+    fn applies_tile(&self, state: ImageState) -> ImageState
+    {
+        let state = if T::POSITIONAL { state } else { self.0.apply(state) };
+
+        self.1.applies_tile(state)
+    }
+
+    fn applies_position(&self, state: ImageState) -> ImageState
+    {
+        let state = if T::POSITIONAL { self.0.apply(state) } else { state };
+
+        self.1.applies_position(state)
+    }
+
+    fn neighbors(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let item = self.0.neighbor(temperature, rng);
+
+        Self(item, self.1.neighbors(temperature, rng))
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        self.1.placement_info(self.0.placement_info(info))
+    }
+
+    fn warm_started(self, info: &PlacementInfo) -> Self
+    {
+        Self(self.0.warm_started(info), self.1.warm_started(info))
+    }
+}
+
+struct UsefulOps;
+
+impl UsefulOps
+{
+    // nearly identical values hash the same
+    fn quantized(x: f32) -> i64
+    {
+        (x * 1000.0).round() as i64
+    }
+
+    fn hash_lab(color: Lab, hasher: &mut impl Hasher)
+    {
+        [color.l, color.a, color.b].map(Self::quantized).hash(hasher);
+    }
+    fn float_changed(v: f32, temperature: f32, rng: &mut Rng) -> f32
+    {
+        let delta = rng.f32() * 2.0 - 1.0;
+
+        v + (delta * temperature)
+    }
+
+    // empty bounds (high not above low) dont count
+    fn bounds_union(
+        a: Option<(Point2<i32>, Point2<i32>)>,
+        b: (Point2<i32>, Point2<i32>)
+    ) -> Option<(Point2<i32>, Point2<i32>)>
+    {
+        let (low, high) = b;
+
+        if high.x <= low.x || high.y <= low.y
+        {
+            return a;
+        }
+
+        Some(a.map(|(a_low, a_high)|
+        {
+            (a_low.zip(low).map(|(a, b)| a.min(b)), a_high.zip(high).map(|(a, b)| a.max(b)))
+        }).unwrap_or(b))
+    }
+
+    // like 3m12s, only the 2 biggest units
+    fn duration_text(duration: Duration) -> String
+    {
+        let seconds = duration.as_secs();
+
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+        if hours > 0
+        {
+            format!("{hours}h{minutes}m")
+        } else if minutes > 0
+        {
+            format!("{minutes}m{seconds}s")
+        } else
+        {
+            format!("{seconds}s")
+        }
+    }
+
+    // a stride above 1 only looks at every nth pixel and scales the sum back up
+    fn image_difference(
+        a: impl Iterator<Item=Lab>,
+        b: impl Iterator<Item=Lab>,
+        exponent: f32,
+        stride: usize
+    ) -> f64
+    {
+        // summed as f64 so near equal energies of big images still compare properly
+        let sum: f64 = a.zip(b).step_by(stride).map(|(original, changed)|
+        {
+            Self::distance_error(original.distance(changed), exponent) as f64
+        }).sum();
+
+        sum * stride as f64
+    }
+
+    fn distance_error(distance: f32, exponent: f32) -> f32
+    {
+        // the common exponents r way faster without powf
+        if exponent == 0.5
+        {
+            distance.sqrt()
+        } else if exponent == 1.0
+        {
+            distance
+        } else
+        {
+            distance.powf(exponent)
+        }
+    }
+
+    // compares the original against the part of the canvas starting at offset
+    fn image_difference_at(
+        original: &LabImage,
+        canvas: &LabImage,
+        offset: Point2<i32>,
+        exponent: f32,
+        stride: usize
+    ) -> f64
+    {
+        let high = offset + original.size_point().map(|x| x as i32);
+
+        Self::image_difference(
+            original.pixels().copied(),
+            canvas.pixels_between(offset, high).map(|(_, pixel)| *pixel),
+            exponent,
+            stride
+        )
+    }
+
+    // only the gradient around the touched pixels can differ from the base
+    fn gradient_difference_at(
+        gradient: GradientEnergy,
+        canvas: &LabImage,
+        offset: Point2<i32>,
+        touched: Option<(Point2<i32>, Point2<i32>)>
+    ) -> f64
+    {
+        let base = gradient.base;
+
+        let Some((low, high)) = touched else { return base.total };
+
+        // sobel reads the neighbors so the changes spread 1 pixel further
+        let size = gradient.target.size_point().map(|x| x as i32);
+        let low = (low - 1 - offset).zip(size).map(|(x, size)| x.clamp(0, size));
+        let high = (high + 1 - offset).zip(size).map(|(x, size)| x.clamp(0, size));
+
+        let changed = canvas.gradient_magnitude_between(low + offset, high + offset);
+
+        changed.pixels_positions().fold(base.total, |total, (position, changed)|
+        {
+            let position = position + low;
+            let difference = (gradient.target[position] - changed).abs();
+
+            total + (difference - base.difference[position]) as f64
+        })
+    }
+
+    // middle of canvas bounds in full resolution pixels of the input
+    fn input_middle(
+        (low, high): (Point2<i32>, Point2<i32>),
+        margin: Point2<i32>,
+        downscale: f32
+    ) -> Point2<i32>
+    {
+        let middle = (low + high).map(|x| x as f32 / 2.0) - margin.map(|x| x as f32);
+
+        (middle * downscale).map(|x| x as i32)
+    }
+
+    // how far the changed pixels r from the mean of the region under the middle of the placement
+    fn segment_difference_at(
+        segments: &Segments,
+        target: &EnergyTarget,
+        after: &LabImage,
+        (low, high): (Point2<i32>, Point2<i32>)
+    ) -> f64
+    {
+        let middle = Self::input_middle((low, high), target.margin, target.downscale);
+
+        let Some(mean) = segments.mean_at(middle) else { return 0.0 };
+
+        let mut total = 0.0;
+
+        for y in low.y..high.y
+        {
+            for x in low.x..high.x
+            {
+                let position = Point2{x, y};
+                let pixel = after[position];
+
+                if target.canvas[position].distance(pixel) > 0.0
+                {
+                    total += pixel.distance(mean).sqrt() as f64;
+                }
+            }
+        }
+
+        total
+    }
+
+    // sums the coverage under every pixel that changed from before to after
+    fn overlap_at(
+        coverage: &GradientImage,
+        before: &LabImage,
+        after: &LabImage,
+        (low, high): (Point2<i32>, Point2<i32>)
+    ) -> f64
+    {
+        let mut total = 0.0;
+
+        for y in low.y..high.y
+        {
+            for x in low.x..high.x
+            {
+                let position = Point2{x, y};
+
+                if before[position].distance(after[position]) > 0.0
+                {
+                    total += coverage[position] as f64;
+                }
+            }
+        }
+
+        total
+    }
+
+    // 1 - ssim of the lightness in 8x8 blocks, each block counts as many times as it has pixels
+    fn structure_difference_at(original: &LabImage, canvas: &LabImage, offset: Point2<i32>) -> f64
+    {
+        const BLOCK: usize = 8;
+
+        // the usual stabilizing constants for a lightness range of 100
+        let c1 = (0.01 * 100.0_f64).powi(2);
+        let c2 = (0.03 * 100.0_f64).powi(2);
+
+        let (width, height) = (original.width(), original.height());
+
+        let mut total = 0.0;
+
+        for block_y in (0..height).step_by(BLOCK)
+        {
+            for block_x in (0..width).step_by(BLOCK)
+            {
+                let mut sums = [0.0_f64; 5];
+                let mut count = 0.0;
+
+                for y in block_y..(block_y + BLOCK).min(height)
+                {
+                    for x in block_x..(block_x + BLOCK).min(width)
+                    {
+                        let position = Point2{x: x as i32, y: y as i32};
+
+                        let a = original[position].l as f64;
+                        let b = canvas[position + offset].l as f64;
+
+                        sums[0] += a;
+                        sums[1] += b;
+                        sums[2] += a * a;
+                        sums[3] += b * b;
+                        sums[4] += a * b;
+
+                        count += 1.0;
+                    }
+                }
+
+                let [a, b, aa, bb, ab] = sums.map(|x| x / count);
+
+                let variance_a = aa - a * a;
+                let variance_b = bb - b * b;
+                let covariance = ab - a * b;
+
+                let ssim = ((2.0 * a * b + c1) * (2.0 * covariance + c2))
+                    / ((a * a + b * b + c1) * (variance_a + variance_b + c2));
+
+                total += (1.0 - ssim) * count;
+            }
+        }
+
+        total
+    }
+}
+
+#[derive(Clone)]
+struct ImageState
+{
+    image: LabImage,
+    index: Option<usize>,
+    add_image: Option<Arc<LabaImage>>,
+    angle: Option<f32>,
+    blending: Option<Blending>,
+    downscale: f32,
+    too_small: bool,
+    // no pixel of the little image is opaque enough to matter, so it wasnt drawn
+    invisible: bool,
+    // the source image doesnt allow rotating it
+    rotation_locked: bool,
+    // the canvas is a cutout alpha, so only how much the image covers matters
+    coverage_only: bool,
+    touched: Option<(Point2<i32>, Point2<i32>)>,
+    // where the corners of the rotated little image ended up
+    corners: Option<[Point2<f32>; 4]>,
+    prerotated: Option<PreRotated>
+}
+
+// the little image is already rotated by the angle, so placing it is just copying it over
+#[derive(Clone, Copy)]
+struct PreRotated
+{
+    // where the top left of the rotated image is compared to the unrotated one
+    offset: Point2<i32>,
+    size: Point2<usize>
+}
+
+impl ImageState
+{
+    fn new(image: LabImage, downscale: f32) -> Self
+    {
+        Self{
+            image,
+            index: None,
+            add_image: None,
+            angle: None,
+            blending: None,
+            downscale,
+            too_small: false,
+            invisible: false,
+            rotation_locked: false,
+            coverage_only: false,
+            touched: None,
+            corners: None,
+            prerotated: None
+        }
+    }
+}
+
+// parametable? who cares its just a word
+trait Paramable
+{
+    // positional params only move the finished little image around the canvas
+    const POSITIONAL: bool = false;
+
+    fn apply(&self, state: ImageState) -> ImageState;
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self;
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo;
+
+    // takes the value of a previous placement, but only if this one can change at all
+    fn warm_started(self, _info: &PlacementInfo) -> Self
+    where
+        Self: Sized
+    {
+        self
+    }
+}
+
+#[derive(Clone)]
+struct IndexParam<'a>
+{
+    images: &'a dyn ImageSource,
+    index: usize,
+    fixed: bool
+}
+
+impl<'a> IndexParam<'a>
+{
+    fn random(images: &dyn ImageSource, rng: &mut Rng) -> usize
+    {
+        rng.usize(0..images.len())
+    }
+}
+
+impl<'a> Paramable for IndexParam<'a>
+{
+    // only the arc gets cloned, the pixels r copied when something changes them
+    fn apply(&self, mut state: ImageState) -> ImageState
+    {
+        state.index = Some(self.index);
+        state.add_image = Some(self.images.get(self.index));
+        state.rotation_locked = !self.images.rotatable(self.index);
+
+        state
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        PlacementInfo{index: Some(self.index), ..info}
+    }
+
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        if !self.fixed && rng.f32() < temperature
+        {
+            Self{index: rng.usize(0..self.images.len()), ..self}
+        } else
+        {
+            self
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ScaleParam
+{
+    scale: Option<Point2<f32>>,
+    uniform: bool,
+    filter: ScaleFilter,
+    min_pixels: u32
+}
+
+impl ScaleParam
+{
+    fn random(allow: bool, uniform: bool, range: Range<f32>, rng: &mut Rng) -> Option<Point2<f32>>
+    {
+        let mut r = ||
+        {
+            range.start + rng.f32() * (range.end - range.start)
+        };
+
+        allow.then(||
+        {
+            Self::constrained(Point2{
+                x: r(),
+                y: r()
+            }, uniform)
+        })
+    }
+
+    fn constrained(scale: Point2<f32>, uniform: bool) -> Point2<f32>
+    {
+        if uniform
+        {
+            Point2::repeat(scale.x)
+        } else
+        {
+            scale
+        }
+    }
+}
+
+impl Paramable for ScaleParam
+{
+    fn apply(&self, mut state: ImageState) -> ImageState
+    {
+        if self.scale.is_some() || state.downscale != 1.0
+        {
+            let scale = self.scale.unwrap_or(Point2::repeat(1.0)) / state.downscale;
+
+            let raw = state.add_image.as_ref().unwrap();
+
+            let scaled = |size: Point2<usize>, scale: Point2<f32>|
+            {
+                (size.map(|x| x as f32) * scale).map(|x| (x as usize).max(1))
+            };
+
+            let original_size = state.prerotated.map(|prerotated| prerotated.size)
+                .unwrap_or(Point2{x: raw.width(), y: raw.height()});
+            let size = scaled(original_size, scale);
+
+            // counted in full resolution pixels even when the state is downscaled
+            let area = (size.x * size.y) as f32 * state.downscale * state.downscale;
+            state.too_small = self.scale.is_some() && area < self.min_pixels as f32;
+
+            let resized = if let Some(prerotated) = state.prerotated.as_mut()
+            {
+                // a quarter turn swaps which axis of the rotated image the scale stretches
+                let turns = (state.angle.unwrap_or(0.0) / consts::FRAC_PI_2).round() as i32;
+                let scale = if turns % 2 != 0 { Point2{x: scale.y, y: scale.x} } else { scale };
+
+                let rotated_size = scaled(raw.size_point(), scale);
+
+                // rotated around the middle so its still centered on the scaled unrotated image
+                let difference = size.map(|x| x as f32) - rotated_size.map(|x| x as f32);
+                *prerotated = PreRotated{offset: (difference / 2.0).map(|x| x.round() as i32), size};
+
+                raw.resized(rotated_size, self.filter)
+            } else
+            {
+                raw.resized(size, self.filter)
+            };
+
+            state.add_image = Some(Arc::new(resized));
+        }
+
+        state
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        PlacementInfo{scale: self.scale, ..info}
+    }
+
+    fn warm_started(self, info: &PlacementInfo) -> Self
+    {
+        Self{scale: self.scale.and(info.scale).or(self.scale), ..self}
+    }
+
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut change = |v, scale|
+        {
+            UsefulOps::float_changed(v, temperature * scale, rng)
+        };
+
+        let scale = self.scale.map(|value|
+        {
+            Self::constrained(value.map(|x| change(x, 0.5).max(0.05)), self.uniform)
+        });
+
+        Self{scale, ..self}
+    }
+}
+
+#[derive(Clone)]
+struct HueParam(Option<Lab>);
+
+impl HueParam
+{
+    fn random(allow: bool, rng: &mut Rng) -> Option<Lab>
+    {
+        let mut r = |value|
+        {
+            (rng.f32() * 2.0 - 1.0) * value
+        };
+
+        allow.then(||
+        {
+            Lab{l: r(25.0), a: r(50.0), b: r(50.0)}
+        })
+    }
+}
+
+impl Paramable for HueParam
+{
+    fn apply(&self, mut state: ImageState) -> ImageState
+    {
+        if let Some(hue) = self.0
+        {
+            Arc::make_mut(state.add_image.as_mut().unwrap()).pixels_mut().for_each(|pixel|
+            {
+                pixel.l += hue.l;
+                pixel.a += hue.a;
+                pixel.b += hue.b;
+            });
+        }
+
+        state
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        PlacementInfo{hue: self.0, ..info}
+    }
+
+    fn warm_started(self, info: &PlacementInfo) -> Self
+    {
+        Self(self.0.and(info.hue).or(self.0))
+    }
+
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut change = |v, scale|
+        {
+            UsefulOps::float_changed(v, temperature * scale, rng)
+        };
+
+        Self(self.0.map(|value| value.map(|x| change(x, 20.0))))
+    }
+}
+
+#[derive(Clone)]
+struct TransparencyParam
+{
+    transparency: Option<f32>,
+    lower_bound: f32,
+    mode: TransparencyMode
+}
+
+impl TransparencyParam
+{
+    // gets constrained once its made into a param
+    fn random(allow: bool, rng: &mut Rng) -> Option<f32>
+    {
+        allow.then(||
+        {
+            rng.f32() * 2.0 - 1.0
+        })
+    }
+
+    // anything below this would make every pixel hit the lower bound anyway
+    // (or in multiplicative mode scale the alpha below the lower bound)
+    fn constrained(mut self) -> Self
+    {
+        let min = -(1.0 - self.lower_bound);
+
+        self.transparency = self.transparency.map(|x| x.clamp(min, 1.0));
+
+        self
+    }
+}
+
+impl Paramable for TransparencyParam
+{
+    fn apply(&self, mut state: ImageState) -> ImageState
+    {
+        if let Some(transparency) = self.transparency
+        {
+            let lower_bound = self.lower_bound;
+            let mode = self.mode;
+
+            Arc::make_mut(state.add_image.as_mut().unwrap()).pixels_mut().for_each(|pixel|
+            {
+                if pixel.alpha > lower_bound
+                {
+                    pixel.alpha = match mode
+                    {
+                        TransparencyMode::Additive => pixel.alpha + transparency,
+                        // scales the alpha so soft edges stay soft relative to the rest
+                        TransparencyMode::Multiplicative => pixel.alpha * (1.0 + transparency)
+                    }.clamp(lower_bound, 1.0);
+                }
+            });
+        }
+
+        state
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        PlacementInfo{transparency: self.transparency, ..info}
+    }
+
+    fn warm_started(self, info: &PlacementInfo) -> Self
+    {
+        Self{transparency: self.transparency.and(info.transparency).or(self.transparency), ..self}
+    }
+
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut change = |v, scale|
+        {
+            UsefulOps::float_changed(v, temperature * scale, rng)
+        };
+
+        Self{
+            transparency: self.transparency.map(|value| change(value, 0.01)),
+            ..self
+        }.constrained()
+    }
+}
+
+#[derive(Clone)]
+struct AngleParam<'a>
+{
+    images: &'a dyn ImageSource,
+    angle: Option<f32>,
+    mode: AngleMode,
+    steps: u32,
+    // the scale cant stretch the image differently along each axis
+    uniform: bool,
+    edge: EdgeMode,
+    sampling: RotationSampling
+}
+
+impl AngleParam<'_>
+{
+    fn random(allow: bool, mode: AngleMode, steps: u32, rng: &mut Rng) -> Option<f32>
+    {
+        allow.then(||
+        {
+            match mode
+            {
+                AngleMode::Continuous => rng.f32() * (2.0 * consts::PI),
+                AngleMode::Cardinal => Self::random_cardinal(rng),
+                AngleMode::Quantized => Self::random_quantized(steps, rng)
+            }
+        })
+    }
+
+    fn random_cardinal(rng: &mut Rng) -> f32
+    {
+        rng.u8(0..4) as f32 * consts::FRAC_PI_2
+    }
+
+    fn step(steps: u32) -> f32
+    {
+        (2.0 * consts::PI) / steps.max(1) as f32
+    }
+
+    fn random_quantized(steps: u32, rng: &mut Rng) -> f32
+    {
+        rng.u32(0..steps.max(1)) as f32 * Self::step(steps)
+    }
+}
+
+impl Paramable for AngleParam<'_>
+{
+    fn apply(&self, mut state: ImageState) -> ImageState
+    {
+        let angle = self.angle.filter(|_| !state.rotation_locked).unwrap_or(0.0);
+
+        state.angle = Some(angle);
+
+        let steps = match self.mode
+        {
+            AngleMode::Continuous => return state,
+            AngleMode::Cardinal => 4,
+            AngleMode::Quantized => self.steps.max(1)
+        };
+
+        let step = (angle / Self::step(steps)).round() as u32 % steps;
+
+        // a rotated image stretched along its own axes isnt the stretched image rotated,
+        // except for quarter turns where the axes just swap
+        let quarter = (step * 4).is_multiple_of(steps);
+
+        if step == 0 || !(quarter || self.uniform)
+        {
+            return state;
+        }
+
+        // theres only a few angles so the source can keep the rotated images around
+        if let (Some(index), Some(add_image)) = (state.index, state.add_image.take())
+        {
+            let (rotated, offset) = self.images.rotated(index, step).unwrap_or_else(||
+            {
+                let (rotated, offset) = add_image.rotated(angle, self.edge, self.sampling);
+
+                (Arc::new(rotated), offset)
+            });
+
+            state.prerotated = Some(PreRotated{offset, size: add_image.size_point()});
+            state.add_image = Some(rotated);
+        }
+
+        state
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        PlacementInfo{angle: self.angle, ..info}
+    }
+
+    fn warm_started(self, info: &PlacementInfo) -> Self
+    {
+        Self{angle: self.angle.and(info.angle).or(self.angle), ..self}
+    }
+
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let angle = self.angle.map(|value|
+        {
+            match self.mode
+            {
+                AngleMode::Continuous =>
+                {
+                    UsefulOps::float_changed(value, temperature * 0.01, rng) % (2.0 * consts::PI)
+                },
+                AngleMode::Cardinal =>
+                {
+                    if rng.f32() < temperature
+                    {
+                        Self::random_cardinal(rng)
+                    } else
+                    {
+                        value
+                    }
+                },
+                AngleMode::Quantized =>
+                {
+                    if rng.f32() < temperature
+                    {
+                        let step = Self::step(self.steps);
+                        let direction = if rng.bool() { 1.0 } else { -1.0 };
+
+                        (value + step * direction).rem_euclid(2.0 * consts::PI)
+                    } else
+                    {
+                        value
+                    }
+                }
+            }
+        });
+
+        Self{angle, ..self}
+    }
+}
+
+#[derive(Clone)]
+struct BlendParam
+{
+    mode: Option<BlendMode>,
+    conversion: ColorConversion
+}
+
+impl BlendParam
+{
+    fn random(allow: bool, rng: &mut Rng) -> Option<BlendMode>
+    {
+        allow.then(|| Self::random_mode(rng))
+    }
+
+    fn random_mode(rng: &mut Rng) -> BlendMode
+    {
+        let modes = [BlendMode::Normal, BlendMode::Dodge, BlendMode::Burn];
+
+        modes[rng.usize(0..modes.len())]
+    }
+}
+
+impl Paramable for BlendParam
+{
+    fn apply(&self, mut state: ImageState) -> ImageState
+    {
+        state.blending = self.mode.map(|mode| Blending{mode, conversion: self.conversion});
+
+        state
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        PlacementInfo{blend: self.mode, ..info}
+    }
+
+    fn warm_started(self, info: &PlacementInfo) -> Self
+    {
+        Self{mode: self.mode.and(info.blend).or(self.mode), ..self}
+    }
+
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mode = self.mode.map(|mode|
+        {
+            if rng.f32() < temperature
+            {
+                Self::random_mode(rng)
+            } else
+            {
+                mode
+            }
+        });
+
+        Self{mode, ..self}
+    }
+}
+
+#[derive(Clone)]
+struct PositionParam
+{
+    position: Point2<f32>,
+    shadow: Option<Shadow>,
+    stroke: Option<Stroke>,
+    edge: EdgeMode,
+    sampling: RotationSampling,
+    min_alpha: f32,
+    max_opacity: f32
+}
+
+impl PositionParam
+{
+    fn random(rng: &mut Rng) -> Point2<f32>
+    {
+        Point2{
+            x: rng.f32(),
+            y: rng.f32()
+        }
+    }
+}
+
+impl Paramable for PositionParam
+{
+    const POSITIONAL: bool = true;
+
+    fn apply(&self, mut state: ImageState) -> ImageState
+    {
+        let mut add_image = state.add_image.take().unwrap();
+
+        if add_image.pixels().all(|pixel| pixel.alpha < self.min_alpha)
+        {
+            state.invisible = true;
+
+            return state;
+        }
+
+        if self.max_opacity < 1.0
+        {
+            add_image = Arc::new(add_image.as_ref().clone().map(|pixel|
+            {
+                Laba{alpha: pixel.alpha.min(self.max_opacity), ..pixel}
+            }));
+        }
+
+        if state.coverage_only
+        {
+            add_image = Arc::new(add_image.as_ref().clone().map(|pixel|
+            {
+                Laba{l: 1.0, a: 0.0, b: 0.0, alpha: pixel.alpha}
+            }));
+        }
+
+        // already rotated images still get placed like the unrotated one would be
+        let (small_size, offset) = state.prerotated
+            .map(|prerotated| (prerotated.size, prerotated.offset))
+            .unwrap_or((add_image.size_point(), Point2::repeat(0)));
+
+        let size = state.image.size_point();
+        let position = (self.position * size.map(|x| x as f32))
+            .zip(small_size
+                 .zip(size)
+                 .map(|(small_size, total_size)| (total_size as i32 - small_size as i32).max(0)))
+            .map(|(x, limit)| (x as i32).clamp(0, limit));
+
+        let angle = state.angle.unwrap();
+
+        let placed = position + offset;
+        let placed_angle = if state.prerotated.is_some() { 0.0 } else { angle };
+
+        let mut touched = None;
+
+        // shadows dont count as covered
+        if let Some(shadow) = self.shadow.as_ref().filter(|_| !state.coverage_only)
+        {
+            let shadow = Shadow{
+                offset: shadow.offset.map(|x| (x as f32 / state.downscale).round() as i32),
+                ..*shadow
+            };
+
+            let low;
+            let high;
+            (state.image, low, high) = state.image.overlay_shadow_rotated(
+                &add_image,
+                placed,
+                placed_angle,
+                self.edge,
+                self.sampling,
+                &shadow
+            );
+
+            touched = UsefulOps::bounds_union(touched, (low, high));
+        }
+
+        if let Some(stroke) = self.stroke.as_ref()
+        {
+            let stroke = Stroke{
+                width: (stroke.width as f32 / state.downscale).round() as u32,
+                // the outline is part of whats cut out
+                color: if state.coverage_only { Lab{l: 1.0, a: 0.0, b: 0.0} } else { stroke.color }
+            };
+
+            let low;
+            let high;
+            (state.image, low, high) = state.image.overlay_stroke_rotated(
+                &add_image,
+                placed,
+                placed_angle,
+                self.edge,
+                self.sampling,
+                &stroke
+            );
+
+            touched = UsefulOps::bounds_union(touched, (low, high));
+        }
+
+        let low;
+        let high;
+        (state.image, low, high) = state.image.overlay_rotated(
+            &add_image,
+            placed,
+            placed_angle,
+            self.edge,
+            self.sampling,
+            state.blending.filter(|_| !state.coverage_only).unwrap_or_default()
+        );
+
+        state.touched = UsefulOps::bounds_union(touched, (low, high));
+
+        let half = small_size.map(|x| x as f32) / 2.0;
+        let middle = position.map(|x| x as f32) + half;
+
+        let (a_sin, a_cos) = angle.sin_cos();
+        state.corners = Some([(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)|
+        {
+            let offset = Point2{x: half.x * x, y: half.y * y};
+
+            middle + Point2{
+                x: a_cos * offset.x - a_sin * offset.y,
+                y: a_sin * offset.x + a_cos * offset.y
+            }
+        }));
+
+        state
+    }
+
+    fn placement_info(&self, info: PlacementInfo) -> PlacementInfo
+    {
+        PlacementInfo{position: Some(self.position), ..info}
+    }
+
+    fn neighbor(self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut change = |v, scale|
+        {
+            UsefulOps::float_changed(v, temperature * scale, rng)
+        };
+
+        let position = self.position.map(|x|
+        {
+            change(x, 1.0)
+        });
+
+        Self{position, ..self}
+    }
+}
+
+#[derive(Clone, Copy)]
+struct GradientEnergy<'a>
+{
+    target: &'a GradientImage,
+    base: &'a GradientBase,
+    weight: f32
+}
+
+// gradient error of the canvas before a placement, so only the part it changes gets redone
+struct GradientBase
+{
+    difference: GradientImage,
+    total: f64
+}
+
+impl GradientBase
+{
+    fn new(target: &GradientImage, canvas: &LabImage, offset: Point2<i32>) -> Self
+    {
+        let high = offset + target.size_point().map(|x| x as i32);
+        let gradient = canvas.gradient_magnitude_between(offset, high);
+
+        let difference = GradientImage::from_fn(target.width(), target.height(), |position|
+        {
+            (target[position] - gradient[position]).abs()
+        });
+
+        let total = difference.pixels().map(|x| *x as f64).sum();
+
+        Self{difference, total}
+    }
+}
+
+// a target and the canvas placements get compared against it on
+#[derive(Clone, Copy)]
+struct EnergyTarget<'a>
+{
+    original: &'a LabImage,
+    gradient: Option<GradientEnergy<'a>>,
+    canvas: &'a LabImage,
+    coverage: Option<&'a GradientImage>,
+    // always full resolution, positions get scaled into it
+    segments: Option<&'a Segments>,
+    grid: Option<&'a PlacementGrid>,
+    margin: Point2<i32>,
+    // how many full resolution pixels one pixel of the canvas covers
+    downscale: f32
+}
+
+// the gradient weight lives in the targets since it needs a gradient image
+#[derive(Clone, Copy)]
+struct EnergyWeights
+{
+    color: f32,
+    exponent: f32,
+    ssim: f32,
+    segment: f32,
+    overlap: f32,
+    stride: usize
+}
+
+#[derive(Clone)]
+struct ImageAnnealable<'a, N>
+{
+    full: EnergyTarget<'a>,
+    // energy gets measured on this instead of the full canvas if its there
+    downscaled: Option<EnergyTarget<'a>>,
+    weights: EnergyWeights,
+    node: N,
+    // the little image with everything but the position applied for the energy target,
+    // neighbors that only move it around share it
+    tile: Option<(PlacementInfo, Arc<ImageState>)>
+}
+
+impl<'a, N> ImageAnnealable<'a, N>
+{
+    pub fn new(
+        full: EnergyTarget<'a>,
+        downscaled: Option<EnergyTarget<'a>>,
+        weights: EnergyWeights,
+        node: N
+    ) -> Self
+    where
+        N: NodeTrait
+    {
+        Self{full, downscaled, weights, node, tile: None}.with_tile(None)
+    }
+
+    fn tile_key(&self) -> PlacementInfo
+    where
+        N: NodeTrait
+    {
+        PlacementInfo{position: None, ..self.node.placement_info(PlacementInfo::default())}
+    }
+
+    fn with_tile(mut self, previous: Option<&(PlacementInfo, Arc<ImageState>)>) -> Self
+    where
+        N: NodeTrait
+    {
+        let key = self.tile_key();
+
+        let tile = match previous
+        {
+            Some((previous_key, tile)) if *previous_key == key => tile.clone(),
+            _ =>
+            {
+                let target = self.downscaled.as_ref().unwrap_or(&self.full);
+
+                let state = ImageState::new(LabImage::from_raw(Vec::new(), 0, 0), target.downscale);
+
+                Arc::new(self.node.applies_tile(state))
+            }
+        };
+
+        self.tile = Some((key, tile));
+
+        self
+    }
+
+    // same as applying the whole node on the energy target, but reuses the little image
+    fn applied_energy_target(&self) -> ImageState
+    where
+        N: NodeTrait
+    {
+        let target = self.downscaled.as_ref().unwrap_or(&self.full);
+
+        let Some((_, tile)) = self.tile.as_ref() else
+        {
+            return self.applied_on(target);
+        };
+
+        let state = ImageState{image: target.canvas.clone(), ..tile.as_ref().clone()};
+
+        self.node.applies_position(state)
+    }
+
+    pub fn applied(&self) -> ImageState
+    where
+        N: NodeTrait
+    {
+        self.applied_on(&self.full)
+    }
+
+    fn applied_on(&self, target: &EnergyTarget) -> ImageState
+    where
+        N: NodeTrait
+    {
+        self.node.applies(ImageState::new(target.canvas.clone(), target.downscale))
+    }
+
+    // the same placement applied to a cutout alpha instead of the canvas
+    fn covered(&self, alpha: &LabImage) -> LabImage
+    where
+        N: NodeTrait
+    {
+        let state = ImageState{coverage_only: true, ..ImageState::new(alpha.clone(), 1.0)};
+
+        self.node.applies(state).image
+    }
+}
+
+impl<'a, N> Annealable for ImageAnnealable<'a, N>
+where
+    N: NodeTrait + Clone
+{
+    fn random_neighbor(&self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut output = self.clone();
+
+        output.node = output.node.neighbors(temperature, rng);
+
+        output.with_tile(self.tile.as_ref())
+    }
+
+    fn state_hash(&self) -> Option<u64>
+    {
+        Some(self.node.placement_info(PlacementInfo::default()).quantized_hash())
+    }
+
+    fn energy(&self) -> f64
+    {
+        let target = self.downscaled.as_ref().unwrap_or(&self.full);
+
+        let state = self.applied_energy_target();
+
+        if state.too_small || state.invisible
+        {
+            return f64::INFINITY;
+        }
+
+        if let (Some(grid), Some(touched)) = (target.grid, state.touched)
+        {
+            if grid.is_full(UsefulOps::input_middle(touched, target.margin, target.downscale))
+            {
+                return f64::INFINITY;
+            }
+        }
+
+        let pixels = state.image;
+
+        let weights = self.weights;
+
+        // zero weights skip their term completely
+        let mut energy = 0.0;
+
+        if weights.color != 0.0
+        {
+            let difference = UsefulOps::image_difference_at(
+                target.original,
+                &pixels,
+                target.margin,
+                weights.exponent,
+                weights.stride
+            );
+
+            energy += weights.color as f64 * difference;
+        }
+
+        if let Some(gradient) = target.gradient
+        {
+            let gradient_difference = UsefulOps::gradient_difference_at(
+                gradient,
+                &pixels,
+                target.margin,
+                state.touched
+            );
+
+            energy += gradient.weight as f64 * gradient_difference;
+        }
+
+        if weights.ssim != 0.0
+        {
+            let structure_difference = UsefulOps::structure_difference_at(
+                target.original,
+                &pixels,
+                target.margin
+            );
+
+            energy += weights.ssim as f64 * structure_difference;
+        }
+
+        if let (Some(segments), Some(touched)) = (target.segments, state.touched)
+        {
+            let segment_difference = UsefulOps::segment_difference_at(
+                segments,
+                target,
+                &pixels,
+                touched
+            );
+
+            energy += weights.segment as f64 * segment_difference;
+        }
+
+        if let (Some(coverage), Some(touched)) = (target.coverage, state.touched)
+        {
+            let overlap = UsefulOps::overlap_at(coverage, target.canvas, &pixels, touched);
+
+            energy += weights.overlap as f64 * overlap;
+        }
+
+        energy
+    }
+}
+
+#[derive(Clone)]
+struct BackgroundAnnealable<'a>
+{
+    original: &'a LabImage,
+    color: Lab,
+    exponent: f32
+}
+
+impl<'a> Debug for BackgroundAnnealable<'a>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("BackgroundAnnealable")
+            .field("color", &self.color)
+            .finish()
+    }
+}
+
+impl<'a> BackgroundAnnealable<'a>
+{
+    pub fn new(original: &'a LabImage, color: Lab, exponent: f32) -> Self
+    {
+        Self{original, color, exponent}
+    }
+
+    pub fn applied(&self) -> LabImage
+    {
+        LabImage::repeat(
+            self.color,
+            self.original.width(),
+            self.original.height()
+        )
+    }
+}
+
+impl<'a> Annealable for BackgroundAnnealable<'a>
+{
+    fn random_neighbor(&self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut change = |v|
+        {
+            UsefulOps::float_changed(v, temperature, rng)
+        };
+
+        let c = self.color;
+
+        let mut output = self.clone();
+
+        output.color = Lab{l: change(c.l), a: change(c.a), b: change(c.b)};
+
+        output
+    }
+
+    fn state_hash(&self) -> Option<u64>
+    {
+        let mut hasher = DefaultHasher::new();
+        UsefulOps::hash_lab(self.color, &mut hasher);
+
+        Some(hasher.finish())
+    }
+
+    fn energy(&self) -> f64
+    {
+        let pixels = self.applied();
+
+        UsefulOps::image_difference(
+            self.original.pixels().copied(),
+            pixels.pixels().copied(),
+            self.exponent,
+            1
+        )
+    }
+}
+
+// linear gradient between 2 colors along some direction
+#[derive(Clone)]
+struct GradientBackgroundAnnealable<'a>
+{
+    original: &'a LabImage,
+    start: Lab,
+    end: Lab,
+    angle: f32,
+    exponent: f32
+}
+
+impl<'a> Debug for GradientBackgroundAnnealable<'a>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("GradientBackgroundAnnealable")
+            .field("start", &self.start)
+            .field("end", &self.end)
+            .field("angle", &self.angle)
+            .finish()
+    }
+}
+
+impl<'a> GradientBackgroundAnnealable<'a>
+{
+    // starts flat and top to bottom, like most skies
+    pub fn new(original: &'a LabImage, color: Lab, exponent: f32) -> Self
+    {
+        Self{original, start: color, end: color, angle: consts::FRAC_PI_2, exponent}
+    }
+
+    pub fn applied(&self) -> LabImage
+    {
+        self.rendered(self.original.size_point(), Point2::repeat(0))
+    }
+
+    // the gradient spans the original, anything past it (like the margin) keeps the end colors
+    pub fn rendered(&self, size: Point2<usize>, offset: Point2<i32>) -> LabImage
+    {
+        let original_size = self.original.size_point().map(|x| x as f32);
+        let middle = original_size.map(|x| x / 2.0);
+
+        let direction = Point2{x: self.angle.cos(), y: self.angle.sin()};
+        let half_length = ((direction.x.abs() * original_size.x + direction.y.abs() * original_size.y) / 2.0)
+            .max(f32::EPSILON);
+
+        LabImage::from_fn(size.x, size.y, |position|
+        {
+            let position = (position - offset).map(|x| x as f32 + 0.5) - middle;
+            let along = position.x * direction.x + position.y * direction.y;
+
+            let t = ((along / half_length + 1.0) / 2.0).clamp(0.0, 1.0);
+
+            self.start.blend(Laba{alpha: t, ..self.end.into()})
+        })
+    }
+}
+
+impl<'a> Annealable for GradientBackgroundAnnealable<'a>
+{
+    fn random_neighbor(&self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut change = |v, scale|
+        {
+            UsefulOps::float_changed(v, temperature * scale, rng)
+        };
+
+        let mut output = self.clone();
+
+        output.start = self.start.map(|x| change(x, 1.0));
+        output.end = self.end.map(|x| change(x, 1.0));
+        output.angle = change(self.angle, 0.01) % (2.0 * consts::PI);
+
+        output
+    }
+
+    fn state_hash(&self) -> Option<u64>
+    {
+        let mut hasher = DefaultHasher::new();
+
+        UsefulOps::hash_lab(self.start, &mut hasher);
+        UsefulOps::hash_lab(self.end, &mut hasher);
+        UsefulOps::quantized(self.angle).hash(&mut hasher);
+
+        Some(hasher.finish())
+    }
+
+    fn energy(&self) -> f64
+    {
+        let pixels = self.applied();
+
+        UsefulOps::image_difference(
+            self.original.pixels().copied(),
+            pixels.pixels().copied(),
+            self.exponent,
+            1
+        )
+    }
+}
+
+// a coarse grid of colors blended smoothly into each other
+#[derive(Clone)]
+struct BlockBackgroundAnnealable<'a>
+{
+    original: &'a LabImage,
+    cells: Point2<usize>,
+    colors: Vec<Lab>,
+    exponent: f32
+}
+
+impl<'a> Debug for BlockBackgroundAnnealable<'a>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_struct("BlockBackgroundAnnealable")
+            .field("cells", &self.cells)
+            .field("colors", &self.colors)
+            .finish()
+    }
+}
+
+impl<'a> BlockBackgroundAnnealable<'a>
+{
+    pub fn new(original: &'a LabImage, color: Lab, cells: u32, exponent: f32) -> Self
+    {
+        let cells = Point2::repeat(cells.max(1) as usize);
+
+        Self{original, cells, colors: vec![color; cells.x * cells.y], exponent}
+    }
+
+    pub fn applied(&self) -> LabImage
+    {
+        LabImage::from_raw(self.colors.clone(), self.cells.x, self.cells.y)
+            .resized_bilinear(self.original.size_point())
+    }
+
+    // anything past the original (like the margin) repeats the closest edge
+    pub fn rendered(&self, size: Point2<usize>, offset: Point2<i32>) -> LabImage
+    {
+        let applied = self.applied();
+        let high = applied.size_point().map(|x| x as i32 - 1);
+
+        LabImage::from_fn(size.x, size.y, |position|
+        {
+            applied[(position - offset).zip(high).map(|(x, high)| x.clamp(0, high))]
+        })
+    }
+}
+
+impl<'a> Annealable for BlockBackgroundAnnealable<'a>
+{
+    // one cell at a time, every cell mostly only changes its own part of the energy
+    fn random_neighbor(&self, temperature: f32, rng: &mut Rng) -> Self
+    {
+        let mut output = self.clone();
+
+        let cell = &mut output.colors[rng.usize(0..self.colors.len())];
+        *cell = cell.map(|x| UsefulOps::float_changed(x, temperature, rng));
+
+        output
+    }
+
+    fn state_hash(&self) -> Option<u64>
+    {
+        let mut hasher = DefaultHasher::new();
+
+        self.colors.iter().for_each(|color| UsefulOps::hash_lab(*color, &mut hasher));
+
+        Some(hasher.finish())
+    }
+
+    fn energy(&self) -> f64
+    {
+        let pixels = self.applied();
+
+        UsefulOps::image_difference(
+            self.original.pixels().copied(),
+            pixels.pixels().copied(),
+            self.exponent,
+            1
+        )
+    }
+}
+
+pub trait Annealable
+{
+    fn random_neighbor(&self, temperature: f32, rng: &mut Rng) -> Self;
+    fn energy(&self) -> f64;
+
+    // states without a hash r never tabu
+    fn state_hash(&self) -> Option<u64>
+    {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StateEnergy<S>
+{
+    pub state: S,
+    pub energy: f64
+}
+
+impl<S> StateEnergy<S>
+{
+    // nan is treated as the worst energy and ties go to the earliest state
+    pub fn best(states: impl IntoIterator<Item=Self>) -> Option<Self>
+    {
+        states.into_iter().enumerate().min_by(|(a_index, a), (b_index, b)|
+        {
+            a.energy.is_nan().cmp(&b.energy.is_nan())
+                .then_with(|| a.energy.total_cmp(&b.energy))
+                .then_with(|| a_index.cmp(b_index))
+        }).map(|(_, state)| state)
+    }
+}
+
+impl<S: Annealable> StateEnergy<S>
+{
+    fn new(state: S) -> Self
+    {
+        let energy = state.energy();
+
+        Self{state, energy}
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnnealStats
+{
+    pub accepted: u32,
+    pub rejected: u32,
+    pub new_best: u32
+}
+
+impl AnnealStats
+{
+    pub fn merged(self, other: Self) -> Self
+    {
+        Self{
+            accepted: self.accepted + other.accepted,
+            rejected: self.rejected + other.rejected,
+            new_best: self.new_best + other.new_best
+        }
+    }
+
+    // too high means its too hot, too low means its frozen
+    pub fn acceptance_ratio(&self) -> Option<f32>
+    {
+        let total = self.accepted + self.rejected;
+
+        (total > 0).then(|| self.accepted as f32 / total as f32)
+    }
+}
+
+impl fmt::Display for AnnealStats
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self.acceptance_ratio()
+        {
+            Some(ratio) => write!(
+                f,
+                "accepted {:.1}% of moves, {} new bests",
+                ratio * 100.0,
+                self.new_best
+            ),
+            None => write!(f, "no moves")
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Annealer<S>
+{
+    state: StateEnergy<S>,
+    best_neighbor: Option<StateEnergy<S>>,
+    max_temperature: f32,
+    temperature_floor: f32,
+    gamma: f32,
+    strict: bool,
+    // steps without a big enough improvement to the best before giving up, and how big is enough
+    patience: Option<(u32, f64)>,
+    tabu: VecDeque<u64>,
+    tabu_size: usize,
+    batch: usize,
+    threads: usize,
+    cycles: u32,
+    commit: Commit,
+    rng: Rng
+}
+
+// neighbors of a batch can get evaluated on separate threads, so states have to be shareable
+impl<S: Annealable + Clone + Send + Sync> Annealer<S>
+{
+    pub fn new(start: S, max_temperature: f32) -> Self
+    {
+        Self::from_state_energy(StateEnergy::new(start), max_temperature)
+    }
+
+    pub fn from_state_energy(start: StateEnergy<S>, max_temperature: f32) -> Self
+    {
+        Self{
+            state: start,
+            best_neighbor: None,
+            max_temperature,
+            temperature_floor: 0.0,
+            gamma: 1.0,
+            strict: false,
+            patience: None,
+            tabu: VecDeque::new(),
+            tabu_size: 0,
+            batch: 1,
+            threads: 1,
+            cycles: 1,
+            commit: Commit::Best,
+            rng: Rng::new()
+        }
+    }
+
+    // keeps the last steps from being completely frozen
+    pub fn with_temperature_floor(mut self, floor: f32) -> Self
+    {
+        self.temperature_floor = floor;
+
+        self
+    }
+
+    pub fn with_tabu(mut self, size: usize) -> Self
+    {
+        self.tabu_size = size;
+
+        self
+    }
+
+    pub fn with_batch(mut self, batch: usize) -> Self
+    {
+        self.batch = batch.max(1);
+
+        self
+    }
+
+    // how many threads evaluate a batch, the result doesnt depend on it
+    pub fn with_threads(mut self, threads: usize) -> Self
+    {
+        self.threads = threads.max(1);
+
+        self
+    }
+
+    // below 1 its more permissive at low temperatures, above 1 less
+    pub fn with_acceptance_gamma(mut self, gamma: f32) -> Self
+    {
+        self.gamma = gamma;
+
+        self
+    }
+
+    // neighbors exactly at the acceptance limit get rejected too
+    pub fn with_strict_acceptance(mut self, strict: bool) -> Self
+    {
+        self.strict = strict;
+
+        self
+    }
+
+    // stops early once the best energy hasnt dropped by more than epsilon (relative to it)
+    // in the last window steps, 0 steps never stops
+    pub fn with_patience(mut self, window: u32, epsilon: f64) -> Self
+    {
+        self.patience = (window > 0).then_some((window, epsilon));
+
+        self
+    }
+
+    // each cycle heats back up and starts over from the starting state, only the best is shared
+    pub fn with_cycles(mut self, cycles: u32) -> Self
+    {
+        self.cycles = cycles.max(1);
+
+        self
+    }
+
+    // the best state ever seen is the default, the last accepted one can be worse
+    pub fn with_commit(mut self, commit: Commit) -> Self
+    {
+        self.commit = commit;
+
+        self
+    }
+
+    // a seeded rng makes the whole run repeatable
+    pub fn with_rng(mut self, rng: Rng) -> Self
+    {
+        self.rng = rng;
+
+        self
+    }
+
+    pub fn anneal(self, steps: u32) -> S
+    {
+        self.anneal_with_energy(steps).state
+    }
+
+    pub fn anneal_with_energy(self, steps: u32) -> StateEnergy<S>
+    {
+        self.anneal_with_stats(steps).0
+    }
+
+    pub fn anneal_with_stats(mut self, steps: u32) -> (StateEnergy<S>, AnnealStats)
+    {
+        let mut stats = AnnealStats::default();
+
+        let start = (self.cycles > 1).then(|| self.state.clone());
+        let cycle_steps = steps.div_ceil(self.cycles).max(1);
+
+        let mut reference = (0, f64::INFINITY);
+
+        for k in 0..steps
+        {
+            if let (Some((window, epsilon)), Some(best)) = (self.patience, self.best_energy())
+            {
+                let (improved_at, energy) = reference;
+
+                if energy.is_infinite() || best < energy - epsilon * energy.abs()
+                {
+                    reference = (k, best);
+                } else if k - improved_at >= window
+                {
+                    break;
+                }
+            }
+
+            let step = k % cycle_steps;
+
+            if step == 0 && k != 0
+            {
+                if let Some(start) = start.as_ref()
+                {
+                    self.state = start.clone();
+                    self.tabu.clear();
+                }
+            }
+
+            let length = cycle_steps.min(steps - (k - step));
+            let fraction = (step + 1) as f32 / length as f32;
+
+            self.improve(self.temperature(1.0 - fraction), &mut stats);
+        }
+
+        let best = self.best_neighbor.expect("steps must be above 0");
+
+        match self.commit
+        {
+            Commit::Best => (best, stats),
+            Commit::Last => (self.state, stats)
+        }
+    }
+
+    pub fn best_energy(&self) -> Option<f64>
+    {
+        self.best_neighbor.as_ref().map(|best| best.energy)
+    }
+
+    fn temperature(&self, fraction: f32) -> f32
+    {
+        (self.max_temperature * fraction).max(self.temperature_floor)
+    }
+
+    fn do_accept(&self, energy: f64, neighbor_energy: f64, temperature: f32) -> bool
+    {
+        let energy_delta = neighbor_energy - energy;
+        let limit = temperature.powf(self.gamma) as f64;
+
+        if self.strict
+        {
+            energy_delta < limit
+        } else
+        {
+            energy_delta <= limit
+        }
+    }
+
+    fn candidate(&mut self, temperature: f32) -> StateEnergy<S>
+    {
+        if self.batch == 1
+        {
+            return StateEnergy::new(self.state.state.random_neighbor(temperature, &mut self.rng));
+        }
+
+        // drawn up front so the rng sequence doesnt depend on the threads
+        let neighbors: Vec<S> = (0..self.batch).map(|_|
+        {
+            self.state.state.random_neighbor(temperature, &mut self.rng)
+        }).collect();
+
+        let energies = parallel_map(self.threads, neighbors.len() as u32, |i|
+        {
+            neighbors[i as usize].energy()
+        });
+
+        let candidates = neighbors.into_iter().zip(energies).map(|(state, energy)|
+        {
+            StateEnergy{state, energy}
+        });
+
+        StateEnergy::best(candidates).unwrap()
+    }
+
+    fn improve(&mut self, temperature: f32, stats: &mut AnnealStats)
+    {
+        let neighbor = self.candidate(temperature);
+
+        let new_best = self.best_neighbor.is_none()
+            || (neighbor.energy < self.best_neighbor.as_ref().unwrap().energy);
+
+        if new_best
+        {
+            stats.new_best += 1;
+
+            self.best_neighbor = Some(neighbor.clone());
+        }
+
+        let hash = (self.tabu_size > 0).then(|| neighbor.state.state_hash()).flatten();
+
+        if hash.map(|hash| self.tabu.contains(&hash)).unwrap_or(false)
+        {
+            stats.rejected += 1;
+
+            return;
+        }
+
+        if self.do_accept(self.state.energy, neighbor.energy, temperature)
+        {
+            stats.accepted += 1;
+
+            if let Some(hash) = hash
+            {
+                if self.tabu.len() >= self.tabu_size
+                {
+                    self.tabu.pop_front();
+                }
+
+                self.tabu.push_back(hash);
+            }
+
+            self.state = neighbor;
+        } else
+        {
+            stats.rejected += 1;
+        }
+    }
+}
+
+// only ever moves to strictly better neighbors, the temperature just shrinks the steps
+pub struct HillClimber<S>
+{
+    state: StateEnergy<S>,
+    max_temperature: f32,
+    temperature_floor: f32,
+    rng: Rng
+}
+
+impl<S: Annealable> HillClimber<S>
+{
+    pub fn new(start: S, max_temperature: f32) -> Self
+    {
+        Self::from_state_energy(StateEnergy::new(start), max_temperature)
+    }
+
+    pub fn from_state_energy(start: StateEnergy<S>, max_temperature: f32) -> Self
+    {
+        Self{state: start, max_temperature, temperature_floor: 0.0, rng: Rng::new()}
+    }
+
+    pub fn with_temperature_floor(mut self, floor: f32) -> Self
+    {
+        self.temperature_floor = floor;
+
+        self
+    }
+
+    pub fn with_rng(mut self, rng: Rng) -> Self
+    {
+        self.rng = rng;
+
+        self
+    }
+
+    pub fn climb(self, steps: u32) -> S
+    {
+        self.climb_with_energy(steps).state
+    }
+
+    pub fn climb_with_energy(self, steps: u32) -> StateEnergy<S>
+    {
+        self.climb_with_stats(steps).0
+    }
+
+    pub fn climb_with_stats(mut self, steps: u32) -> (StateEnergy<S>, AnnealStats)
+    {
+        let mut stats = AnnealStats::default();
+
+        for k in 0..steps
+        {
+            let fraction = (k + 1) as f32 / steps as f32;
+            let temperature = (self.max_temperature * (1.0 - fraction)).max(self.temperature_floor);
+
+            let neighbor = StateEnergy::new(
+                self.state.state.random_neighbor(temperature, &mut self.rng)
+            );
+
+            if neighbor.energy < self.state.energy
+            {
+                stats.accepted += 1;
+                stats.new_best += 1;
+
+                self.state = neighbor;
+            } else
+            {
+                stats.rejected += 1;
+            }
+        }
+
+        (self.state, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::RotatedImages;
+
+    #[test]
+    fn best_ignores_nan()
+    {
+        let states = [f64::NAN, 3.0, f64::NAN, 1.0, 1.0, 2.0].into_iter().enumerate()
+            .map(|(state, energy)| StateEnergy{state, energy});
+
+        let best = StateEnergy::best(states).unwrap();
+
+        assert_eq!(best.state, 3);
+
+        let all_nan = [f64::NAN, f64::NAN].into_iter().enumerate()
+            .map(|(state, energy)| StateEnergy{state, energy});
+
+        assert_eq!(StateEnergy::best(all_nan).unwrap().state, 0);
+    }
+
+    #[derive(Clone)]
+    struct Flip(u64);
+
+    impl Annealable for Flip
+    {
+        fn random_neighbor(&self, _temperature: f32, _rng: &mut Rng) -> Self
+        {
+            Self(1 - self.0)
+        }
+
+        fn energy(&self) -> f64
+        {
+            0.0
+        }
+
+        fn state_hash(&self) -> Option<u64>
+        {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn tabu_blocks_revisits()
+    {
+        let mut annealer = Annealer::new(Flip(0), 1.0).with_tabu(2);
+
+        annealer.improve(1.0, &mut AnnealStats::default());
+        assert_eq!(annealer.state.state.0, 1);
+
+        annealer.improve(1.0, &mut AnnealStats::default());
+        assert_eq!(annealer.state.state.0, 0);
+
+        // both states r tabu now so it stays put
+        annealer.improve(1.0, &mut AnnealStats::default());
+        assert_eq!(annealer.state.state.0, 0);
+    }
+
+    #[test]
+    fn strict_rejects_equal_energy()
+    {
+        let flipped = |strict|
+        {
+            let mut annealer = Annealer::new(Flip(0), 1.0).with_strict_acceptance(strict);
+            annealer.improve(0.0, &mut AnnealStats::default());
+
+            annealer.state.state.0
+        };
+
+        assert_eq!(flipped(false), 1);
+        assert_eq!(flipped(true), 0);
+    }
+
+    #[derive(Clone)]
+    struct Flat(u32);
+
+    impl Annealable for Flat
+    {
+        fn random_neighbor(&self, _temperature: f32, _rng: &mut Rng) -> Self
+        {
+            Self(self.0 + 1)
+        }
+
+        // only the first few steps improve anything
+        fn energy(&self) -> f64
+        {
+            -(self.0.min(3) as f64)
+        }
+    }
+
+    #[test]
+    fn patience_stops_early()
+    {
+        let (state, stats) = Annealer::new(Flat(0), 1.0)
+            .with_patience(5, 0.0)
+            .with_commit(Commit::Last)
+            .anneal_with_stats(100);
+
+        assert_eq!(state.state.0, 8);
+        assert_eq!(stats.accepted + stats.rejected, 8);
+    }
+
+    #[derive(Clone)]
+    struct Walk(i32);
+
+    impl Annealable for Walk
+    {
+        fn random_neighbor(&self, _temperature: f32, _rng: &mut Rng) -> Self
+        {
+            Self(self.0 + 1)
+        }
+
+        fn energy(&self) -> f64
+        {
+            -self.0 as f64
+        }
+    }
+
+    #[test]
+    fn cycles_restart_from_start()
+    {
+        let walked = |cycles|
+        {
+            Annealer::new(Walk(0), 1.0)
+                .with_cycles(cycles)
+                .with_commit(Commit::Last)
+                .anneal_with_stats(10).0.state.0
+        };
+
+        assert_eq!(walked(1), 10);
+        assert_eq!(walked(2), 5);
+        // cycles of 4 steps, the last one only gets whats left
+        assert_eq!(walked(3), 2);
+    }
+
+    #[derive(Clone)]
+    struct Jump(i64);
+
+    impl Annealable for Jump
+    {
+        fn random_neighbor(&self, _temperature: f32, rng: &mut Rng) -> Self
+        {
+            Self(self.0 + rng.i64(-5..=5))
+        }
+
+        fn energy(&self) -> f64
+        {
+            (self.0 - 40).abs() as f64
+        }
+    }
+
+    #[test]
+    fn batch_same_on_any_threads()
+    {
+        let annealed = |threads|
+        {
+            let (state, stats) = Annealer::new(Jump(0), 5.0)
+                .with_batch(7)
+                .with_threads(threads)
+                .with_rng(Rng::with_seed(11))
+                .anneal_with_stats(50);
+
+            (state.state.0, state.energy, stats.accepted, stats.new_best)
+        };
+
+        assert_eq!(annealed(1), annealed(4));
+        assert_eq!(annealed(1), annealed(7));
+    }
+
+    #[test]
+    fn duration_text_units()
+    {
+        let text = |seconds| UsefulOps::duration_text(Duration::from_secs(seconds));
+
+        assert_eq!(text(7), "7s");
+        assert_eq!(text(192), "3m12s");
+        assert_eq!(text(3 * 3600 + 125), "3h2m");
+    }
+
+    #[test]
+    fn structure_difference()
+    {
+        let mut image = LabImage::repeat(Lab{l: 50.0, a: 0.0, b: 0.0}, 10, 10);
+        image.pixels_mut().step_by(3).for_each(|pixel| pixel.l = 80.0);
+
+        let difference = |canvas: &LabImage|
+        {
+            UsefulOps::structure_difference_at(&image, canvas, Point2::repeat(0))
+        };
+
+        assert!(difference(&image).abs() < 1e-9);
+
+        let flat = LabImage::repeat(Lab{l: 60.0, a: 0.0, b: 0.0}, 10, 10);
+        assert!(difference(&flat) > 1.0);
+    }
+
+    #[test]
+    fn windowed_gradient_matches_full()
+    {
+        let lab = |l| Lab{l, a: 0.0, b: 0.0};
+
+        let target = LabImage::from_fn(12, 9, |position| lab(((position.x * 7 + position.y * 3) % 11) as f32 * 5.0))
+            .gradient_magnitude();
+
+        let offset = Point2::repeat(2);
+        let canvas = LabImage::from_fn(16, 13, |position| lab((position.x * position.y % 5) as f32 * 9.0));
+
+        let base = GradientBase::new(&target, &canvas, offset);
+        let gradient = GradientEnergy{target: &target, base: &base, weight: 1.0};
+
+        let full = |changed: &LabImage|
+        {
+            let high = offset + target.size_point().map(|x| x as i32);
+
+            target.pixels().zip(changed.gradient_magnitude().pixels_between(offset, high))
+                .map(|(original, (_, changed))| (original - changed).abs() as f64)
+                .sum::<f64>()
+        };
+
+        assert!((UsefulOps::gradient_difference_at(gradient, &canvas, offset, None) - full(&canvas)).abs() < 0.01);
+
+        // in the middle, over the margin and right at the corner
+        [
+            (Point2{x: 5, y: 4}, Point2{x: 9, y: 7}),
+            (Point2{x: 0, y: 0}, Point2{x: 3, y: 4}),
+            (Point2{x: 12, y: 9}, Point2{x: 16, y: 13})
+        ].into_iter().for_each(|(low, high)|
+        {
+            let mut changed = canvas.clone();
+            changed.pixels_positions_mut().for_each(|(position, pixel)|
+            {
+                if (low.x..high.x).contains(&position.x) && (low.y..high.y).contains(&position.y)
+                {
+                    *pixel = lab(100.0);
+                }
+            });
+
+            let windowed = UsefulOps::gradient_difference_at(gradient, &changed, offset, Some((low, high)));
+
+            assert!((windowed - full(&changed)).abs() < 0.01, "{windowed} {}", full(&changed));
+        });
+    }
+
+    #[test]
+    fn scale_neighbor_seeded()
+    {
+        let param = ScaleParam{
+            scale: Some(Point2{x: 1.0, y: 2.0}),
+            uniform: false,
+            filter: ScaleFilter::Nearest,
+            min_pixels: 0
+        };
+
+        let scale = |param: ScaleParam, seed|
+        {
+            param.neighbor(0.4, &mut Rng::with_seed(seed)).scale.unwrap()
+        };
+
+        let changed = scale(param.clone(), 5);
+
+        assert_eq!(changed, scale(param.clone(), 5));
+        assert!((changed.x - 1.0).abs() <= 0.2 && (changed.y - 2.0).abs() <= 0.2);
+
+        let uniform = ScaleParam{uniform: true, ..param};
+        let tiny = ScaleParam{scale: Some(Point2::repeat(0.05)), ..uniform.clone()};
+
+        (0..100).for_each(|seed|
+        {
+            let changed = scale(uniform.clone(), seed);
+            assert_eq!(changed.x, changed.y);
+
+            assert!(scale(tiny.clone(), seed).x >= 0.05);
+        });
+    }
+
+    #[test]
+    fn position_neighbor_seeded()
+    {
+        let param = PositionParam{
+            position: Point2{x: 0.5, y: 0.25},
+            shadow: None,
+            stroke: None,
+            edge: EdgeMode::Transparent,
+            sampling: RotationSampling::Nearest,
+            min_alpha: 0.0,
+            max_opacity: 1.0
+        };
+
+        let position = |temperature, seed|
+        {
+            param.clone().neighbor(temperature, &mut Rng::with_seed(seed)).position
+        };
+
+        assert_eq!(position(0.0, 3), param.position);
+
+        (0..100).for_each(|seed|
+        {
+            let changed = position(0.1, seed);
+            assert_eq!(changed, position(0.1, seed));
+
+            let moved = (changed - param.position).map(f32::abs);
+            assert!(moved.x <= 0.1 && moved.y <= 0.1);
+        });
+
+        assert_ne!(position(0.1, 1), position(0.1, 2));
+    }
+
+    const TEST_BACKGROUND: Lab = Lab{l: 40.0, a: 0.0, b: 0.0};
+
+    fn test_config() -> CollagerConfig
+    {
+        CollagerConfig{
+            steps: 20,
+            background_steps: 1,
+            background_starts: 1,
+            amount: 4,
+            auto_amount: None,
+            starts: 2,
+            greedy_candidates: 0,
+            tabu_size: 0,
+            neighbor_batch: 1,
+            commit: Commit::Best,
+            optimizer: Optimizer::Annealing,
+            starting_temperature: 0.4,
+            temperature_floor: 1e-4,
+            acceptance_gamma: 1.0,
+            strict_acceptance: false,
+            patience: 0,
+            patience_epsilon: 0.0,
+            cooling_cycles: 1,
+            flat_background: false,
+            background_color: Some(TEST_BACKGROUND),
+            background: Background::Solid,
+            background_init: BackgroundInit::Random,
+            background_cells: 4,
+            warm_start: false,
+            margin: 0,
+            allow_scaling: true,
+            allow_nonuniform_scaling: true,
+            scale_min: 0.5,
+            scale_max: 1.5,
+            scale_decay: ScaleDecay::Constant,
+            min_placement_pixels: 0,
+            allow_rotation: true,
+            angle_mode: AngleMode::Continuous,
+            angle_steps: 16,
+            allow_hue: true,
+            allow_blend_modes: false,
+            allow_transparency: true,
+            transparency_lower_bound: 0.05,
+            transparency_mode: TransparencyMode::Additive,
+            min_alpha: 0.0,
+            max_tile_opacity: 1.0,
+            scaling_unlock: 0.0,
+            rotation_unlock: 0.0,
+            hue_unlock: 0.0,
+            transparency_unlock: 0.0,
+            color_weight: 1.0,
+            gradient_weight: 0.0,
+            ssim_weight: 0.0,
+            segment_weight: 0.0,
+            segment_step: 10.0,
+            grid_cap: None,
+            grid_cells: 4,
+            overlap_weight: 0.0,
+            distance_exponent: 0.5,
+            shadow: None,
+            stroke: None,
+            vignette: 0.0,
+            die_cut: false,
+            edge_mode: EdgeMode::Transparent,
+            rotation_sampling: RotationSampling::Nearest,
+            scale_filter: ScaleFilter::Nearest,
+            conversion: ColorConversion::default(),
+            stop: None,
+            tile_size: None,
+            tile_overlap: 0,
+            energy_downscale: 1,
+            energy_stride: 1,
+            threads: Some(1),
+            seed: Some(5),
+            checkpoint: None,
+            energy_report: false,
+            stats: false,
+            debug: false,
+            debug_every: 1,
+            debug_boxes: false
+        }
+    }
+
+    fn test_collager(config: CollagerConfig) -> Collager
+    {
+        let target = LabImage::from_fn(24, 16, |position|
+        {
+            Lab{l: 30.0 + position.x as f32 * 2.0, a: position.y as f32 * 3.0 - 20.0, b: 10.0}
+        });
+
+        Collager::from_lab(config, target)
+    }
+
+    fn test_images() -> Vec<Arc<LabaImage>>
+    {
+        (0..3).map(|i|
+        {
+            let image = LabaImage::from_fn(6 + i * 2, 5, |position|
+            {
+                let alpha = if (position.x + position.y) % 4 == 0 { 0.5 } else { 1.0 };
+
+                Laba{l: 20.0 + i as f32 * 25.0, a: position.x as f32 * 4.0, b: -10.0, alpha}
+            });
+
+            Arc::new(image)
+        }).collect()
+    }
+
+    fn same_pixels(a: &LabImage, b: &LabImage) -> bool
+    {
+        a.width() == b.width() && a.height() == b.height()
+            && a.pixels().zip(b.pixels()).all(|(a, b)| a == b)
+    }
+
+    #[test]
+    fn plan_renders_collage()
+    {
+        let images = test_images();
+
+        [1, 3].into_iter().for_each(|threads|
+        {
+            let collager = test_collager(CollagerConfig{threads: Some(threads), ..test_config()});
+
+            let mut infos = Vec::new();
+            let (output, _) = collager.collage_canvas(&images, None, 4, |_| None, |progress|
+            {
+                infos.push(progress.info.unwrap());
+            });
+
+            assert_eq!(infos.len(), 4);
+
+            let planned = collager.render_plan(TEST_BACKGROUND, &infos, &images);
+
+            assert!(same_pixels(&output, &planned));
+        });
+    }
+
+    #[test]
+    fn single_placement_renders()
+    {
+        let images = test_images();
+        let collager = test_collager(test_config());
+
+        let (output, info) = collager.best_single_placement(&images);
+
+        assert!(info.index.is_some() && info.position.is_some());
+        assert_eq!(collager.best_single_placement(&images).1, info);
+
+        let planned = collager.render_plan(TEST_BACKGROUND, &[info], &images);
+
+        assert!(same_pixels(&output, &planned));
+    }
+
+    #[test]
+    fn stamp_places_each_once()
+    {
+        let images: Vec<_> = test_images().into_iter().cycle().take(5).collect();
+        let collager = test_collager(test_config());
+
+        let mut indices = Vec::new();
+        collager.stamp_with_progress(&images, |progress|
+        {
+            indices.push(progress.info.unwrap().index.unwrap());
+        });
+
+        indices.sort_unstable();
+
+        assert_eq!(indices, (0..images.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cached_rotations_match()
+    {
+        let images = test_images();
+
+        [(AngleMode::Cardinal, 4), (AngleMode::Quantized, 8)].into_iter().for_each(|(angle_mode, steps)|
+        {
+            let cached = RotatedImages::new(images.clone(), steps, EdgeMode::Transparent, RotationSampling::Nearest, 4);
+
+            let collager = test_collager(CollagerConfig{angle_mode, angle_steps: steps, ..test_config()});
+
+            let (output, _) = collager.collage_canvas(&images, None, 4, |_| None, |_| {});
+            let (cached_output, _) = collager.collage_canvas(&cached, None, 4, |_| None, |_| {});
+
+            assert!(same_pixels(&output, &cached_output));
+        });
+    }
+
+    fn checkpoint_path(name: &str) -> PathBuf
+    {
+        std::env::temp_dir().join(format!("annealingcollager_{name}_{}.accp", std::process::id()))
+    }
+
+    // everything the checkpoint has to carry gets turned on
+    fn checkpoint_config() -> CollagerConfig
+    {
+        CollagerConfig{
+            warm_start: true,
+            scale_decay: ScaleDecay::Linear,
+            grid_cap: Some(3),
+            grid_cells: 2,
+            overlap_weight: 0.5,
+            die_cut: true,
+            energy_downscale: 2,
+            ..test_config()
+        }
+    }
+
+    #[test]
+    fn checkpoint_resume_matches()
+    {
+        static STOP: AtomicBool = AtomicBool::new(false);
+
+        let images = test_images();
+        let (amount, stop_after) = (5, 2);
+
+        let uninterrupted = test_collager(checkpoint_config());
+
+        let mut expected = Vec::new();
+        let (expected_output, expected_cutout) = uninterrupted.collage_canvas(&images, None, amount, |_| None, |progress|
+        {
+            expected.push(progress.info.unwrap());
+        });
+
+        let path = checkpoint_path("resume");
+        let collager = test_collager(CollagerConfig{
+            checkpoint: Some(path.clone()),
+            stop: Some(&STOP),
+            ..checkpoint_config()
+        });
+
+        let mut infos = Vec::new();
+        collager.collage_canvas(&images, None, amount, |_| None, |progress|
+        {
+            infos.push(progress.info.unwrap());
+
+            if progress.placed == stop_after
+            {
+                STOP.store(true, Ordering::Relaxed);
+            }
+        });
+
+        assert_eq!(infos.len(), stop_after as usize);
+
+        STOP.store(false, Ordering::Relaxed);
+
+        let checkpoint = collager.load_checkpoint(&path).unwrap();
+        assert_eq!(checkpoint.placed(), stop_after);
+
+        let resume = Some(Resume::Checkpoint(Box::new(checkpoint)));
+        let (output, cutout) = collager.collage_canvas(&images, resume, amount, |_| None, |progress|
+        {
+            infos.push(progress.info.unwrap());
+        });
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(infos, expected);
+        assert!(same_pixels(&output, &expected_output));
+        assert!(same_pixels(&cutout.unwrap().alpha, &expected_cutout.unwrap().alpha));
+    }
+
+    #[test]
+    fn checkpoint_rejects_mismatch()
+    {
+        let images = test_images();
+
+        let path = checkpoint_path("mismatch");
+        let collager = test_collager(CollagerConfig{checkpoint: Some(path.clone()), ..checkpoint_config()});
+
+        collager.collage_canvas(&images, None, 1, |_| None, |_| {});
+
+        assert!(collager.load_checkpoint(&path).is_ok());
+
+        let rejected = |config: CollagerConfig|
+        {
+            test_collager(config).load_checkpoint(&path).err()
+                .map(|err| err.kind() == io::ErrorKind::InvalidData)
+                .unwrap_or(false)
+        };
+
+        assert!(rejected(CollagerConfig{die_cut: false, ..checkpoint_config()}));
+        assert!(rejected(CollagerConfig{overlap_weight: 0.0, ..checkpoint_config()}));
+        assert!(rejected(CollagerConfig{energy_downscale: 1, ..checkpoint_config()}));
+        assert!(rejected(CollagerConfig{grid_cap: None, ..checkpoint_config()}));
+        assert!(rejected(CollagerConfig{grid_cells: 3, ..checkpoint_config()}));
+        assert!(rejected(CollagerConfig{margin: 2, ..checkpoint_config()}));
+
+        // version comes right after the magic
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[4..8].copy_from_slice(&(Checkpoint::VERSION - 1).to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+
+        assert!(rejected(checkpoint_config()));
+
+        bytes[0] = b'X';
+        fs::write(&path, &bytes).unwrap();
+
+        assert!(rejected(checkpoint_config()));
+
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -1,11 +1,150 @@
 use image::{Rgb, Rgba};
 
+use fastrand::Rng;
+
+use crate::{ColorSpace, WhitePoint, BlendMode};
+
 
 fn lerp(a: f32, b: f32, t: f32) -> f32
 {
     a * (1.0 - t) + b * t
 }
 
+pub fn srgb_to_linear(value: f32) -> f32
+{
+    if value <= 0.04045
+    {
+        value / 12.92
+    } else
+    {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32
+{
+    if value > 0.0031308
+    {
+        1.055 * (value.powf(1.0 / 2.4)) - 0.055
+    } else
+    {
+        12.92 * value
+    }.clamp(0.0, 1.0)
+}
+
+pub fn quantized(rgb: Rgb<f32>) -> Rgb<u8>
+{
+    Rgb(rgb.0.map(|x| (x.clamp(0.0, 1.0) * u8::MAX as f32) as u8))
+}
+
+// oklab coordinates get scaled up to roughly the lab ranges, so the images keep
+// storing Lab and everything tuned for lab (temperatures, hue shifts) still works
+const OKLAB_SCALE: f32 = 100.0;
+
+// how rgb gets turned into the stored Lab pixels and back
+#[derive(Debug, Clone, Copy)]
+pub struct ColorConversion
+{
+    pub space: ColorSpace,
+    // oklab is always relative to d65 so this only matters for lab
+    pub white: WhitePoint
+}
+
+impl Default for ColorConversion
+{
+    fn default() -> Self
+    {
+        Self{space: ColorSpace::Lab, white: WhitePoint::D65}
+    }
+}
+
+// how a placed pixel gets combined with whats under it
+#[derive(Debug, Clone, Copy)]
+pub struct Blending
+{
+    pub mode: BlendMode,
+    // dodge and burn r defined on rgb so they need a way to get there and back
+    pub conversion: ColorConversion
+}
+
+impl Default for Blending
+{
+    fn default() -> Self
+    {
+        Self{mode: BlendMode::Normal, conversion: ColorConversion::default()}
+    }
+}
+
+impl ColorConversion
+{
+    pub fn lab_from_rgb(self, rgb: Rgb<f32>) -> Lab
+    {
+        match self.space
+        {
+            ColorSpace::Lab => Lab::from_rgb_white(rgb, self.white),
+            ColorSpace::Oklab => Oklab::from(rgb).packed()
+        }
+    }
+
+    pub fn laba_from_rgba(self, rgba: Rgba<f32>) -> Laba
+    {
+        let [r, g, b, alpha] = rgba.0;
+
+        Laba{
+            alpha: alpha.clamp(0.0, 1.0),
+            ..Laba::from(self.lab_from_rgb(Rgb([r, g, b])))
+        }
+    }
+
+    pub fn rgb_from_lab(self, lab: Lab) -> Rgb<f32>
+    {
+        match self.space
+        {
+            ColorSpace::Lab => lab.to_rgb_white(self.white),
+            ColorSpace::Oklab => Rgb::from(Oklab::unpacked(lab))
+        }
+    }
+}
+
+impl WhitePoint
+{
+    fn reference(self) -> Xyz
+    {
+        match self
+        {
+            Self::D65 => Xyz{x: 95.047, y: 100.0, z: 108.883},
+            Self::D50 => Xyz{x: 96.422, y: 100.0, z: 82.521}
+        }
+    }
+
+    // srgb is defined relative to d65, so other whites need a bradford adaptation
+    fn adapted(self, xyz: Xyz) -> Xyz
+    {
+        match self
+        {
+            Self::D65 => xyz,
+            Self::D50 => xyz.transformed([
+                [1.0478112, 0.0228866, -0.0501270],
+                [0.0295424, 0.9904844, -0.0170491],
+                [-0.0092345, 0.0150436, 0.7521316]
+            ])
+        }
+    }
+
+    fn unadapted(self, xyz: Xyz) -> Xyz
+    {
+        match self
+        {
+            Self::D65 => xyz,
+            Self::D50 => xyz.transformed([
+                [0.9555766, -0.0230393, 0.0631636],
+                [-0.0282895, 1.0099416, 0.0210077],
+                [0.0122982, -0.0204830, 1.3299098]
+            ])
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Laba
 {
@@ -26,6 +165,11 @@ impl Laba
         }
     }
 
+    pub fn grayscale(self) -> Self
+    {
+        Self{a: 0.0, b: 0.0, ..self}
+    }
+
     pub fn blend(self, other: Laba) -> Laba
     {
         if self.alpha == 0.0
@@ -70,16 +214,7 @@ impl From<Lab> for Rgb<u8>
 {
     fn from(value: Lab) -> Self
     {
-        let rgb = Rgb::from(value);
-
-        let inner: Vec<u8> = rgb.0.into_iter().map(|x: f32|
-        {
-            (x.clamp(0.0, 1.0) * u8::MAX as f32) as u8
-        }).collect();
-        
-        let inner: [u8; 3] = inner.try_into().unwrap();
-
-        Rgb::from(inner)
+        quantized(Rgb::from(value))
     }
 }
 
@@ -87,9 +222,7 @@ impl From<Lab> for Rgb<f32>
 {
     fn from(value: Lab) -> Self
     {
-        let xyz = Xyz::from(value);
-
-        Self::from(xyz)
+        value.to_rgb_white(WhitePoint::D65)
     }
 }
 
@@ -97,6 +230,16 @@ impl From<Lab> for Xyz
 {
     fn from(value: Lab) -> Self
     {
+        Self::from_lab(value, WhitePoint::D65)
+    }
+}
+
+impl Xyz
+{
+    fn from_lab(value: Lab, white: WhitePoint) -> Self
+    {
+        let white = white.reference();
+
         let l_rev = (value.l + 16.0) / 116.0;
 
         let delta = 6.0_f32 / 29.0;
@@ -111,9 +254,9 @@ impl From<Lab> for Xyz
             }
         };
 
-        let x = 95.0489 * f_inv(l_rev + value.a / 500.0);
-        let y = 100.0 * f_inv(l_rev);
-        let z = 108.884 * f_inv(l_rev - value.b / 200.0);
+        let x = white.x * f_inv(l_rev + value.a / 500.0);
+        let y = white.y * f_inv(l_rev);
+        let z = white.z * f_inv(l_rev - value.b / 200.0);
 
         Self{x, y, z}
     }
@@ -135,7 +278,7 @@ impl From<Rgba<f32>> for Laba
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Lab
 {
     pub l: f32,
@@ -145,11 +288,11 @@ pub struct Lab
 
 impl Lab
 {
-    pub fn random() -> Self
+    pub fn random(rng: &mut Rng) -> Self
     {
-        let r = |value|
+        let mut r = |value|
         {
-            (fastrand::f32() * 2.0 - 1.0) * value
+            (rng.f32() * 2.0 - 1.0) * value
         };
 
         Self{l: r(25.0) + 50.0, a: r(50.0), b: r(50.0)}
@@ -183,12 +326,226 @@ impl Lab
             b: lerp(self.b, other.b, other.alpha)
         }
     }
+
+    pub fn blend_with(self, other: Laba, blending: Blending) -> Lab
+    {
+        let operation: fn(f32, f32) -> f32 = match blending.mode
+        {
+            BlendMode::Normal => return self.blend(other),
+            BlendMode::Dodge => |base, top|
+            {
+                if top >= 1.0 { 1.0 } else { (base / (1.0 - top)).min(1.0) }
+            },
+            BlendMode::Burn => |base, top|
+            {
+                if top <= 0.0 { 0.0 } else { 1.0 - ((1.0 - base) / top).min(1.0) }
+            }
+        };
+
+        if other.alpha == 0.0
+        {
+            return self;
+        }
+
+        let conversion = blending.conversion;
+        let rgb = |lab| conversion.rgb_from_lab(lab).0.map(|x: f32| x.clamp(0.0, 1.0));
+
+        let base = rgb(self);
+        let top = rgb(other.no_alpha());
+
+        let blended = conversion.lab_from_rgb(Rgb([0, 1, 2].map(|i| operation(base[i], top[i]))));
+
+        // the alpha still fades between the untouched and the blended color
+        self.blend(Laba{l: blended.l, a: blended.a, b: blended.b, alpha: other.alpha})
+    }
+}
+
+// centroid of the biggest cluster after a few rounds of k-means
+pub fn dominant_color(colors: &[Lab], clusters: usize) -> Option<Lab>
+{
+    if colors.is_empty() || clusters == 0
+    {
+        return None;
+    }
+
+    let step = (colors.len() / clusters).max(1);
+    let mut centroids: Vec<Lab> = colors.iter().step_by(step).take(clusters).copied().collect();
+
+    let closest = |centroids: &[Lab], color: Lab| -> usize
+    {
+        centroids.iter().enumerate().min_by(|(_, a), (_, b)|
+        {
+            a.distance(color).total_cmp(&b.distance(color))
+        }).map(|(index, _)| index).unwrap()
+    };
+
+    let mut counts = vec![0_usize; centroids.len()];
+
+    for _ in 0..10
+    {
+        let mut sums = vec![Lab{l: 0.0, a: 0.0, b: 0.0}; centroids.len()];
+        counts.iter_mut().for_each(|count| *count = 0);
+
+        colors.iter().for_each(|&color|
+        {
+            let index = closest(&centroids, color);
+
+            let sum = &mut sums[index];
+            sum.l += color.l;
+            sum.a += color.a;
+            sum.b += color.b;
+
+            counts[index] += 1;
+        });
+
+        centroids.iter_mut().zip(sums).zip(counts.iter()).for_each(|((centroid, sum), &count)|
+        {
+            if count > 0
+            {
+                *centroid = sum.map(|x| x / count as f32);
+            }
+        });
+    }
+
+    counts.iter().enumerate().max_by_key(|(_, count)| **count).map(|(index, _)| centroids[index])
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Oklab
+{
+    pub l: f32,
+    pub a: f32,
+    pub b: f32
+}
+
+impl Oklab
+{
+    pub fn distance(&self, other: Oklab) -> f32
+    {
+        self.packed().distance(other.packed()) / OKLAB_SCALE.powi(2)
+    }
+
+    pub fn map<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(f32) -> f32
+    {
+        Self{
+            l: f(self.l),
+            a: f(self.a),
+            b: f(self.b)
+        }
+    }
+
+    pub fn blend(self, other: Oklaba) -> Oklab
+    {
+        Self::unpacked(self.packed().blend(other.packed()))
+    }
+
+    pub fn packed(self) -> Lab
+    {
+        Lab{l: self.l, a: self.a, b: self.b}.map(|x| x * OKLAB_SCALE)
+    }
+
+    pub fn unpacked(lab: Lab) -> Self
+    {
+        let lab = lab.map(|x| x / OKLAB_SCALE);
+
+        Self{l: lab.l, a: lab.a, b: lab.b}
+    }
+}
+
+impl From<Rgb<f32>> for Oklab
+{
+    fn from(value: Rgb<f32>) -> Self
+    {
+        let [r, g, b] = value.0.map(srgb_to_linear);
+
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+        Self{
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s
+        }
+    }
+}
+
+impl From<Oklab> for Rgb<f32>
+{
+    fn from(value: Oklab) -> Self
+    {
+        let l = (value.l + 0.3963377774 * value.a + 0.2158037573 * value.b).powi(3);
+        let m = (value.l - 0.1055613458 * value.a - 0.0638541728 * value.b).powi(3);
+        let s = (value.l - 0.0894841775 * value.a - 1.2914855480 * value.b).powi(3);
+
+        let r = 4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s;
+        let g = -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s;
+        let b = -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s;
+
+        Rgb([r, g, b].map(linear_to_srgb))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Oklaba
+{
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+    pub alpha: f32
+}
+
+impl Oklaba
+{
+    pub fn no_alpha(self) -> Oklab
+    {
+        Oklab{l: self.l, a: self.a, b: self.b}
+    }
+
+    pub fn blend(self, other: Oklaba) -> Oklaba
+    {
+        Self::unpacked(self.packed().blend(other.packed()))
+    }
+
+    pub fn packed(self) -> Laba
+    {
+        Laba{alpha: self.alpha, ..Laba::from(self.no_alpha().packed())}
+    }
+
+    pub fn unpacked(laba: Laba) -> Self
+    {
+        let lab = Oklab::unpacked(laba.no_alpha());
+
+        Self{l: lab.l, a: lab.a, b: lab.b, alpha: laba.alpha}
+    }
+}
+
+impl From<Rgba<f32>> for Oklaba
+{
+    fn from(value: Rgba<f32>) -> Self
+    {
+        let conversion = ColorConversion{space: ColorSpace::Oklab, ..Default::default()};
+
+        Self::unpacked(conversion.laba_from_rgba(value))
+    }
 }
 
 impl From<Xyz> for Lab
 {
     fn from(value: Xyz) -> Self
     {
+        Self::from_xyz(value, WhitePoint::D65)
+    }
+}
+
+impl Lab
+{
+    fn from_xyz(value: Xyz, white: WhitePoint) -> Self
+    {
+        let white = white.reference();
+
         let delta = 6.0_f32 / 29.0;
         let delta_cube = delta.powi(3);
         let lower_scale = 1.0 / (delta.powi(2) * 3.0);
@@ -204,9 +561,9 @@ impl From<Xyz> for Lab
             }
         };
 
-        let x = f(value.x / 95.047);
-        let y = f(value.y / 100.0);
-        let z = f(value.z / 108.883);
+        let x = f(value.x / white.x);
+        let y = f(value.y / white.y);
+        let z = f(value.z / white.z);
 
         let l = 116.0 * y - 16.0;
         let a = 500.0 * (x - y);
@@ -214,6 +571,16 @@ impl From<Xyz> for Lab
 
         Self{l, a, b}
     }
+
+    pub fn from_rgb_white(value: Rgb<f32>, white: WhitePoint) -> Self
+    {
+        Self::from_xyz(white.adapted(Xyz::from(value)), white)
+    }
+
+    pub fn to_rgb_white(self, white: WhitePoint) -> Rgb<f32>
+    {
+        Rgb::from(white.unadapted(Xyz::from_lab(self, white)))
+    }
 }
 
 impl From<Rgb<f32>> for Lab
@@ -234,6 +601,13 @@ struct Xyz
 
 impl Xyz
 {
+    fn transformed(self, matrix: [[f32; 3]; 3]) -> Self
+    {
+        let [x, y, z] = matrix.map(|[a, b, c]| a * self.x + b * self.y + c * self.z);
+
+        Self{x, y, z}
+    }
+
     pub fn map<F>(self, mut f: F) -> Self
     where
         F: FnMut(f32) -> f32 
@@ -252,15 +626,7 @@ impl From<Rgb<f32>> for Xyz
     {
         let f = |value: f32| -> f32
         {
-            let value = if value <= 0.04045
-            {
-                value / 12.92
-            } else
-            {
-                ((value + 0.055) / 1.055).powf(2.4)
-            };
-
-            value * 100.0
+            srgb_to_linear(value) * 100.0
         };
 
         let r = f(value.0[0]);
@@ -283,15 +649,7 @@ impl From<Xyz> for Rgb<f32>
 
         let f = |a: f32, b: f32, c: f32| -> f32
         {
-            let x = value.x * a + value.y * b + value.z * c;
-
-            if x > 0.0031308
-            {
-                1.055 * (x.powf(1.0 / 2.4)) - 0.055
-            } else
-            {
-                12.92 * x
-            }.clamp(0.0, 1.0)
+            linear_to_srgb(value.x * a + value.y * b + value.z * c)
         };
 
         let r = f(3.2406, -1.5372, -0.4986);
@@ -312,6 +670,26 @@ mod tests
         assert!((a - b).abs() < 0.001, "a: {}, b: {}", a, b);
     }
 
+    #[test]
+    fn dodge_and_burn()
+    {
+        let base = Lab{l: 50.0, a: 0.0, b: 0.0};
+        let top = Laba{l: 40.0, a: 0.0, b: 0.0, alpha: 1.0};
+
+        let blended = |mode, alpha|
+        {
+            let blending = Blending{mode, ..Default::default()};
+
+            base.blend_with(Laba{alpha, ..top}, blending).l
+        };
+
+        close_enough(blended(BlendMode::Normal, 1.0), 40.0);
+        assert!(blended(BlendMode::Dodge, 1.0) > 50.0);
+        assert!(blended(BlendMode::Burn, 1.0) < 40.0);
+
+        close_enough(blended(BlendMode::Dodge, 0.0), 50.0);
+    }
+
     #[test]
     fn xyz_to_lab()
     {
@@ -340,6 +718,64 @@ mod tests
         close_enough(xyz.z, 58.190);
     }
 
+    #[test]
+    fn dominant()
+    {
+        let red = Lab{l: 50.0, a: 60.0, b: 40.0};
+        let blue = Lab{l: 30.0, a: 20.0, b: -70.0};
+
+        let colors: Vec<_> = (0..100).map(|i|
+        {
+            let color = if i % 4 == 0 { blue } else { red };
+
+            color.map(|x| x + (i % 3) as f32 * 0.1)
+        }).collect();
+
+        let color = dominant_color(&colors, 3).unwrap();
+
+        assert!(color.distance(red) < 1.0, "{color:?}");
+    }
+
+    #[test]
+    fn oklab_roundtrip()
+    {
+        let white = Oklab::from(Rgb::from([1.0, 1.0, 1.0]));
+
+        close_enough(white.l, 1.0);
+        close_enough(white.a, 0.0);
+        close_enough(white.b, 0.0);
+
+        let conversion = ColorConversion{space: ColorSpace::Oklab, ..Default::default()};
+
+        let rgb = Rgb::from([0.3, 0.6, 0.9]);
+        let back_rgb = conversion.rgb_from_lab(conversion.lab_from_rgb(rgb));
+
+        rgb.0.iter().zip(back_rgb.0.iter()).for_each(|(&a, &b)|
+        {
+            close_enough(a, b);
+        });
+    }
+
+    #[test]
+    fn white_points_roundtrip()
+    {
+        [WhitePoint::D65, WhitePoint::D50].into_iter().for_each(|white|
+        {
+            // white should stay neutral no matter the reference white
+            let lab = Lab::from_rgb_white(Rgb::from([1.0, 1.0, 1.0]), white);
+
+            assert!((lab.l - 100.0).abs() < 0.05 && lab.a.abs() < 0.05 && lab.b.abs() < 0.05, "{lab:?}");
+
+            let rgb = Rgb::from([0.3, 0.6, 0.9]);
+            let back_rgb = Lab::from_rgb_white(rgb, white).to_rgb_white(white);
+
+            rgb.0.iter().zip(back_rgb.0.iter()).for_each(|(&a, &b)|
+            {
+                close_enough(a, b);
+            });
+        });
+    }
+
     #[test]
     fn rgb_to_lab()
     {
@@ -8,8 +8,17 @@ use std::{
     num::{ParseIntError, ParseFloatError}
 };
 
-use crate::complain;
+use image::{Rgb, imageops::FilterType};
 
+use crate::Lab;
+
+
+pub fn complain(message: impl Display) -> !
+{
+    eprintln!("{message}");
+
+    process::exit(1)
+}
 
 #[allow(dead_code)]
 enum ArgError
@@ -398,6 +407,22 @@ impl_displayable_default!{i32}
 impl_displayable_default!{i64}
 impl_displayable_default!{i128}
 
+impl DisplayableDefault for Lab
+{
+    fn display_default(&self) -> Option<String>
+    {
+        None
+    }
+}
+
+impl DisplayableDefault for LittleSize
+{
+    fn display_default(&self) -> Option<String>
+    {
+        Some(format!("{}x{}", self.width, self.height))
+    }
+}
+
 impl<T: DisplayableDefault> DisplayableDefault for Option<T>
 {
     fn display_default(&self) -> Option<String>
@@ -425,11 +450,15 @@ trait ParsableEnum
 
 macro_rules! iterable_enum
 {
-    (enum $enum_name:ident
-    {
-        $($key:ident),+
-    }) =>
+    (
+        $(#[$attribute:meta])*
+        enum $enum_name:ident
+        {
+            $($key:ident),+
+        }
+    ) =>
     {
+        $(#[$attribute])*
         pub enum $enum_name
         {
             $($key,)+
@@ -483,7 +512,7 @@ macro_rules! iterable_enum
 
                         let tail = raw.chars().skip(1).flat_map(|c|
                         {
-                            if c.is_lowercase()
+                            if !c.is_uppercase()
                             {
                                 vec![c]
                             } else
@@ -523,6 +552,182 @@ iterable_enum!
     }
 }
 
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum EdgeMode
+    {
+        Transparent,
+        Clamp,
+        Wrap
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum RotationSampling
+    {
+        Nearest,
+        Bilinear
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum ScaleFilter
+    {
+        Nearest,
+        Bilinear,
+        Bicubic
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum ScaleDecay
+    {
+        Constant,
+        Linear,
+        Exponential
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum ResizeFilter
+    {
+        Nearest,
+        Triangle,
+        CatmullRom,
+        Gaussian,
+        Lanczos3
+    }
+}
+
+impl From<ResizeFilter> for FilterType
+{
+    fn from(value: ResizeFilter) -> Self
+    {
+        match value
+        {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3
+        }
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum AngleMode
+    {
+        Continuous,
+        Cardinal,
+        Quantized
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum TransparencyMode
+    {
+        Additive,
+        Multiplicative
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum ColorSpace
+    {
+        Lab,
+        Oklab
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum WhitePoint
+    {
+        D65,
+        D50
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum Optimizer
+    {
+        Annealing,
+        HillClimbing
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum BlendMode
+    {
+        Normal,
+        Dodge,
+        Burn
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Commit
+    {
+        Best,
+        Last
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum TileShape
+    {
+        Rect,
+        Circle,
+        Ellipse
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum Background
+    {
+        Solid,
+        Gradient,
+        Blocks
+    }
+}
+
+iterable_enum!
+{
+    #[derive(Debug, Clone, Copy)]
+    enum BackgroundInit
+    {
+        Random,
+        Average,
+        Dominant
+    }
+}
+
 impl<T: ParsableEnum> ParsableInner for T
 {
     fn parse_inner(value: &str) -> Result<Self, ArgError>
@@ -569,6 +774,64 @@ impl ParsableInner for u32
     }
 }
 
+impl ParsableInner for u64
+{
+    fn parse_inner(value: &str) -> Result<Self, ArgError>
+    {
+        value.parse::<u64>().map_err(|err| (value, err).into())
+    }
+}
+
+impl ParsableInner for i32
+{
+    fn parse_inner(value: &str) -> Result<Self, ArgError>
+    {
+        value.parse::<i32>().map_err(|err| (value, err).into())
+    }
+}
+
+// hex rgb like ff8800
+impl ParsableInner for Lab
+{
+    fn parse_inner(value: &str) -> Result<Self, ArgError>
+    {
+        let hex = value.strip_prefix('#').unwrap_or(value);
+
+        if hex.len() != 6 || !hex.is_ascii()
+        {
+            return Err(ArgError::Parse(value.to_owned()));
+        }
+
+        let channel = |index: usize|
+        {
+            u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+                .map(|x| x as f32 / u8::MAX as f32)
+                .map_err(|err| ArgError::from((value, err)))
+        };
+
+        Ok(Lab::from(Rgb::from([channel(0)?, channel(1)?, channel(2)?])))
+    }
+}
+
+// either a single size like 64 or width and height like 64x128
+impl ParsableInner for LittleSize
+{
+    fn parse_inner(value: &str) -> Result<Self, ArgError>
+    {
+        let (width, height) = value.split_once('x').unwrap_or((value, value));
+
+        let width = u32::parse_inner(width)?;
+        let height = u32::parse_inner(height)?;
+
+        if width == 0 || height == 0
+        {
+            return Err(ArgError::Parse(value.to_owned()));
+        }
+
+        Ok(Self{width, height})
+    }
+}
+
 impl ParsableInner for f32
 {
     fn parse_inner(value: &str) -> Result<Self, ArgError>
@@ -612,22 +875,120 @@ impl<T: ParsableInner + DisplayableDefault> ArgParsable for Option<T>
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct LittleSize
+{
+    pub width: u32,
+    pub height: u32
+}
+
 pub struct Config
 {
-    pub directory: String,
+    pub directory: Option<String>,
+    pub image_list: Option<String>,
+    pub images: Option<String>,
+    pub frames: Option<String>,
+    pub frame_stride: usize,
+    pub lazy_cache: Option<usize>,
+    pub max_memory: Option<u64>,
     pub input: String,
     pub output: String,
+    pub quality: Option<u32>,
+    pub resume: Option<String>,
+    pub resume_placed: u32,
+    pub checkpoint: Option<String>,
+    pub load_checkpoint: Option<String>,
+    pub seed: Option<u64>,
     pub max_size: Option<u32>,
-    pub little_size: Option<u32>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub little_size: Option<LittleSize>,
+    pub match_histogram: bool,
+    pub tile_shape: TileShape,
+    pub color_space: ColorSpace,
+    pub white_point: WhitePoint,
+    pub resize_filter: ResizeFilter,
+    pub linear_resize: bool,
     pub steps: u32,
+    pub background_steps: u32,
+    pub background_starts: u32,
     pub amount: u32,
+    pub auto_amount: Option<f32>,
     pub starts: u32,
+    pub greedy_candidates: u32,
+    pub tabu_size: usize,
+    pub neighbor_batch: usize,
+    pub commit: Commit,
+    pub optimizer: Optimizer,
     pub starting_temperature: f32,
+    pub temperature_floor: f32,
+    pub acceptance_gamma: f32,
+    pub strict_acceptance: bool,
+    pub patience: u32,
+    pub patience_epsilon: f32,
+    pub cooling_cycles: u32,
+    pub flat_background: bool,
+    pub background_color: Option<Lab>,
+    pub background: Background,
+    pub background_init: BackgroundInit,
+    pub background_cells: u32,
+    pub warm_start: bool,
+    pub margin: u32,
+    pub threads: Option<usize>,
+    pub tile_size: Option<u32>,
+    pub tile_overlap: u32,
+    pub energy_downscale: u32,
+    pub energy_stride: usize,
     pub allow_hue: bool,
+    pub allow_blend_modes: bool,
     pub allow_transparency: bool,
+    pub transparency_lower_bound: f32,
+    pub transparency_mode: TransparencyMode,
+    pub min_alpha: f32,
+    pub max_tile_opacity: f32,
     pub allow_rotation: bool,
+    pub angle_mode: AngleMode,
+    pub angle_steps: u32,
+    pub no_rotate_list: Option<String>,
     pub allow_scaling: bool,
-    pub debug: bool
+    pub allow_nonuniform_scaling: bool,
+    pub scale_min: f32,
+    pub scale_max: f32,
+    pub scale_decay: ScaleDecay,
+    pub min_placement_pixels: u32,
+    pub scaling_unlock: f32,
+    pub rotation_unlock: f32,
+    pub hue_unlock: f32,
+    pub transparency_unlock: f32,
+    pub color_weight: f32,
+    pub gradient_weight: f32,
+    pub ssim_weight: f32,
+    pub segment_weight: f32,
+    pub segment_step: f32,
+    pub grid_cap: Option<u32>,
+    pub grid_cells: u32,
+    pub overlap_weight: f32,
+    pub distance_exponent: f32,
+    pub shadow: bool,
+    pub shadow_offset: i32,
+    pub shadow_darkness: f32,
+    pub stroke: bool,
+    pub stroke_width: u32,
+    pub stroke_color: Option<Lab>,
+    pub vignette: f32,
+    pub die_cut: bool,
+    pub edge_mode: EdgeMode,
+    pub rotation_sampling: RotationSampling,
+    pub scale_filter: ScaleFilter,
+    pub single_placement: bool,
+    pub stamp: bool,
+    pub voronoi_cells: Option<u32>,
+    pub voronoi_jitter: f32,
+    pub energy_report: bool,
+    pub stats: bool,
+    pub debug: bool,
+    pub debug_every: u32,
+    pub debug_boxes: bool
 }
 
 impl Config
@@ -635,36 +996,218 @@ impl Config
     pub fn parse(args: impl Iterator<Item=String>) -> Self
     {
         let mut directory = None;
+        let mut image_list = None;
+        let mut images = None;
+        let mut frames = None;
+        let mut frame_stride = 1;
+        let mut lazy_cache = None;
+        let mut max_memory = None;
         let mut input = None;
         let mut output = "output.png".to_owned();
+        let mut quality = None;
+        let mut resume = None;
+        let mut resume_placed = 0;
+        let mut checkpoint = None;
+        let mut load_checkpoint = None;
+        let mut seed = None;
         let mut max_size = None;
+        let mut max_width = None;
+        let mut max_height = None;
         let mut little_size = None;
+        let mut match_histogram = false;
+        let mut tile_shape = TileShape::Rect;
+        let mut color_space = ColorSpace::Lab;
+        let mut white_point = WhitePoint::D65;
+        let mut resize_filter = ResizeFilter::CatmullRom;
+        let mut linear_resize = true;
         let mut steps = 100_u32;
+        let mut background_steps = 50_u32;
+        let mut background_starts = 1;
         let mut amount = 100_u32;
+        let mut auto_amount = None;
         let mut starts = 3;
+        let mut greedy_candidates = 1;
+        let mut tabu_size = 0;
+        let mut neighbor_batch = 1;
+        let mut commit = Commit::Best;
+        let mut optimizer = Optimizer::Annealing;
+        let mut tile_size = None;
+        let mut tile_overlap = 16;
+        let mut energy_downscale = 1;
+        let mut energy_stride = 1;
         let mut starting_temperature = 0.4;
+        let mut temperature_floor = 1e-4;
+        let mut acceptance_gamma = 1.0;
+        let mut strict_acceptance = false;
+        let mut patience = 0;
+        let mut patience_epsilon = 0.0001;
+        let mut cooling_cycles = 1;
+        let mut flat_background = false;
+        let mut background_color = None;
+        let mut background = Background::Solid;
+        let mut background_init = BackgroundInit::Random;
+        let mut background_cells = 4;
+        let mut warm_start = false;
+        let mut margin = 0;
+        let mut threads = None;
         let mut allow_hue = true;
+        let mut allow_blend_modes = false;
         let mut allow_transparency = true;
+        let mut transparency_lower_bound = 0.05;
+        let mut transparency_mode = TransparencyMode::Additive;
+        let mut min_alpha = 0.01;
+        let mut max_tile_opacity = 1.0;
         let mut allow_rotation = true;
+        let mut angle_mode = AngleMode::Continuous;
+        let mut angle_steps = 16;
+        let mut no_rotate_list = None;
         let mut allow_scaling = true;
+        let mut allow_nonuniform_scaling = true;
+        let mut scale_min = 0.5;
+        let mut scale_max = 1.5;
+        let mut scale_decay = ScaleDecay::Constant;
+        let mut min_placement_pixels = 0;
+        let mut scaling_unlock = 0.0;
+        let mut rotation_unlock = 0.0;
+        let mut hue_unlock = 0.0;
+        let mut transparency_unlock = 0.0;
+        let mut color_weight = 1.0;
+        let mut gradient_weight = 0.0;
+        let mut ssim_weight = 0.0;
+        let mut segment_weight = 0.0;
+        let mut segment_step = 10.0;
+        let mut grid_cap = None;
+        let mut grid_cells = 4;
+        let mut overlap_weight = 0.0;
+        let mut distance_exponent = 0.5;
+        let mut shadow = false;
+        let mut shadow_offset = 4;
+        let mut shadow_darkness = 0.5;
+        let mut stroke = false;
+        let mut stroke_width = 2;
+        let mut stroke_color = None;
+        let mut vignette = 0.0;
+        let mut die_cut = false;
+        let mut edge_mode = EdgeMode::Transparent;
+        let mut rotation_sampling = RotationSampling::Nearest;
+        let mut scale_filter = ScaleFilter::Nearest;
+        let mut single_placement = false;
+        let mut stamp = false;
+        let mut voronoi_cells = None;
+        let mut voronoi_jitter = 1.0;
+        let mut energy_report = false;
+        let mut stats = false;
         let mut debug = false;
+        let mut debug_every = 1;
+        let mut debug_boxes = false;
 
         let mut parser = ArgParser::new();
 
-        parser.push_required(&mut directory, 'd', "directory", "input directory containing the images");
-        parser.push_required(&mut input, 'i', "input", "input");
-        parser.push(&mut output, 'o', "output", "output path");
+        parser.push(&mut directory, 'd', "directory", "input directory containing the images, theyre used in sorted path order");
+        parser.push(&mut image_list, None, "image-list", "file with a path to an image on each line");
+        parser.push(&mut images, None, "images", "glob pattern of images to use (like tiles/**/*.jpg)");
+        parser.push(&mut frames, None, "frames", "animated gif, png or webp whose frames r used as images");
+        parser.push(&mut frame_stride, None, "frame-stride", "only use every nth frame of the animation");
+        parser.push(&mut lazy_cache, None, "lazy-cache", "load images only when theyre used, keeping at most this many in memory");
+        parser.push(&mut max_memory, None, "max-memory", "memory budget in megabytes, images get loaded lazily to fit in it or it errors if they cant");
+        parser.push_required(&mut input, 'i', "input", "input image path, - reads it from stdin");
+        parser.push(&mut output, 'o', "output", "output path (png, jpg or webp), - writes a png to stdout");
+        parser.push(&mut quality, None, "quality", "quality of lossy output from 1 to 100");
+        parser.push(&mut resume, None, "resume", "previous output to continue collaging on top of");
+        parser.push(&mut resume_placed, None, "resume-placed", "how many images the resumed output already has");
+        parser.push(&mut checkpoint, None, "checkpoint", "file the whole run state gets saved to after every placement");
+        parser.push(&mut load_checkpoint, None, "load-checkpoint", "checkpoint to continue exactly where it stopped (same config needed)");
+        parser.push(&mut seed, None, "seed", "seed for the random numbers, same seed and config give the same output");
         parser.push(&mut max_size, 'S', "size", "max size of the input image");
-        parser.push(&mut little_size, None, "little-size", "max size of the directory images");
+        parser.push(&mut max_width, None, "max-width", "max width of the input image");
+        parser.push(&mut max_height, None, "max-height", "max height of the input image");
+        parser.push(&mut little_size, None, "little-size", "size of the directory images, either one number or width and height like 64x128");
+        parser.push_flag(&mut match_histogram, None, "match-histogram", "shift the colors of the little images to match the input before placing them", true);
+        parser.push(&mut tile_shape, None, "tile-shape", format!("shape the directory images get cut into, options: {}", TileShape::list_all()));
+        parser.push(&mut color_space, None, "color-space", format!("space the colors r compared and blended in, options: {}", ColorSpace::list_all()));
+        parser.push(&mut white_point, None, "white-point", format!("reference white of the lab colors, options: {}", WhitePoint::list_all()));
+        parser.push(&mut resize_filter, None, "resize-filter", format!("filter used when resizing the input and directory images, options: {}", ResizeFilter::list_all()));
+        parser.push_flag(&mut linear_resize, None, "gamma-resize", "resize the directory images without linearizing them first (shrunk images come out darker)", false);
         parser.push(&mut steps, 's', "steps", "amount of steps to anneal for");
+        parser.push(&mut background_steps, None, "background-steps", "amount of steps to anneal the background color for");
+        parser.push(&mut background_starts, None, "background-starts", "how many independent times to anneal the background, the best one is kept");
         parser.push(&mut amount, 'a', "amount", "amount of images to use in the final collage");
+        parser.push(&mut auto_amount, None, "auto-amount", "amount of images per megapixel of the input (overrides amount)");
         parser.push(&mut starts, None, "starts", "how many times to restart the annealer for each little image");
         parser.push(&mut starting_temperature, 't', "temperature", "starting temperature for little images annealing");
+        parser.push(&mut temperature_floor, None, "temperature-floor", "lowest temperature the annealing cools down to");
+        parser.push(&mut acceptance_gamma, None, "acceptance-gamma", "worse neighbors get accepted up to temperature to this power");
+        parser.push_flag(&mut strict_acceptance, None, "strict-acceptance", "dont accept neighbors that r exactly at the limit, so equal energy moves stop at zero temperature", true);
+        parser.push(&mut patience, None, "patience", "stop annealing a little image after this many steps without improving (0 never stops early)");
+        parser.push(&mut patience_epsilon, None, "patience-epsilon", "smallest improvement (as a fraction of the energy) that resets the patience");
+        parser.push(&mut cooling_cycles, None, "cooling-cycles", "how many times the annealing of each placement heats back up and starts over");
+        parser.push_flag(&mut flat_background, None, "flat-background", "use the average color of the input as the background instead of annealing it", true);
+        parser.push(&mut background_color, None, "background-color", "hex rgb color of the background (implies flat background)");
+        parser.push(&mut background, None, "background", format!("what kind of background gets annealed, options: {}", Background::list_all()));
+        parser.push(&mut background_cells, None, "background-cells", "cells along each side of a blocks background");
+        parser.push_flag(&mut warm_start, None, "warm-start", "start annealing each little image from the scale, rotation and colors of the last one", true);
+        parser.push(&mut background_init, None, "background-init", format!("starting color of the background annealing, options: {}", BackgroundInit::list_all()));
+        parser.push(&mut margin, None, "margin", "pixels of background around the input that little images can extend into");
+        parser.push(&mut greedy_candidates, None, "greedy-candidates", "how many random placements to try before annealing, the best one is annealed");
+        parser.push(&mut tile_size, None, "tile-size", "anneal separate square parts of the input this big (for huge outputs)");
+        parser.push(&mut tile_overlap, None, "tile-overlap", "how far the parts overlap to hide the seams");
+        parser.push(&mut energy_downscale, None, "energy-downscale", "compare placements at this many times lower resolution (faster, output stays full resolution)");
+        parser.push(&mut energy_stride, None, "energy-stride", "only compare every nth pixel while annealing (faster, less accurate)");
+        parser.push(&mut tabu_size, None, "tabu-size", "how many recently accepted states the annealer refuses to go back to (0 is off)");
+        parser.push(&mut commit, None, "commit", format!("which annealed state gets placed, the best one found or the last accepted one, options: {}", Commit::list_all()));
+        parser.push(&mut neighbor_batch, None, "neighbor-batch", "neighbors the annealer evaluates in parallel each step, moving to the best one");
+        parser.push(&mut optimizer, None, "optimizer", format!("how the background and placements get optimized, options: {}", Optimizer::list_all()));
+        parser.push(&mut threads, None, "threads", "amount of threads for the restarts (default all cores)");
+        parser.push(&mut angle_mode, None, "angle-mode", format!("how the little images get rotated, options: {}", AngleMode::list_all()));
+        parser.push(&mut angle_steps, None, "angle-steps", "how many evenly spaced angles the quantized angle mode can pick from");
+        parser.push(&mut no_rotate_list, None, "no-rotate-list", "file with a path on each line to images that never get rotated (like text or faces)");
         parser.push_flag(&mut allow_rotation, None, "disable-rotation", "disallow rotating the little images", false);
         parser.push_flag(&mut allow_scaling, None, "disable-scaling", "disallow scaling the little images", false);
+        parser.push_flag(&mut allow_nonuniform_scaling, None, "disable-nonuniform-scaling", "keep the proportions of the little images when scaling", false);
         parser.push_flag(&mut allow_hue, None, "disable-hue", "disallow changing color of the little images", false);
+        parser.push_flag(&mut allow_blend_modes, None, "blend-modes", format!("let each little image pick how its blended, options: {}", BlendMode::list_all()), true);
         parser.push_flag(&mut allow_transparency, None, "disable-transparency", "disallow changing opacity of little images", false);
+        parser.push(&mut transparency_lower_bound, None, "transparency-lower-bound", "lowest opacity that changing the transparency can go to");
+        parser.push(&mut transparency_mode, None, "transparency-mode", format!("how transparency changes the alpha of the little images, multiplicative keeps their own alpha shape, options: {}", TransparencyMode::list_all()));
+        parser.push(&mut min_alpha, None, "min-alpha", "little images that never get more opaque than this r skipped instead of placed");
+        parser.push(&mut max_tile_opacity, None, "max-tile-opacity", "no part of a placed little image gets more opaque than this, so the background always shows through");
+        parser.push(&mut scale_min, None, "scale-min", "smallest starting scale of the little images");
+        parser.push(&mut scale_max, None, "scale-max", "biggest starting scale of the little images");
+        parser.push(&mut scale_decay, None, "scale-decay", format!("how the biggest scale shrinks over the placements, options: {}", ScaleDecay::list_all()));
+        parser.push(&mut min_placement_pixels, None, "min-placement-pixels", "scaled little images with fewer pixels than this never get placed");
+        parser.push(&mut scaling_unlock, None, "scaling-unlock", "fraction of the placements after which scaling is allowed");
+        parser.push(&mut rotation_unlock, None, "rotation-unlock", "fraction of the placements after which rotation is allowed");
+        parser.push(&mut hue_unlock, None, "hue-unlock", "fraction of the placements after which changing color is allowed");
+        parser.push(&mut transparency_unlock, None, "transparency-unlock", "fraction of the placements after which changing opacity is allowed");
+        parser.push(&mut color_weight, None, "color-weight", "how much matching the colors of the input matters (0 turns it off)");
+        parser.push(&mut gradient_weight, None, "gradient-weight", "how much matching the edges of the input matters compared to colors");
+        parser.push(&mut ssim_weight, None, "ssim-weight", "how much matching the local structure (ssim of the lightness) matters, its at most 2 per pixel");
+        parser.push(&mut segment_weight, None, "segment-weight", "how much placed pixels get pulled towards the mean color of their region of the input (0 is off)");
+        parser.push(&mut segment_step, None, "segment-step", "size of the color steps the input gets split into regions by");
+        parser.push(&mut grid_cap, None, "grid-cap", "most images that can land in each cell of a coarse grid over the input");
+        parser.push(&mut grid_cells, None, "grid-cells", "cells per side of the grid used by grid-cap");
+        parser.push(&mut overlap_weight, None, "overlap-weight", "penalty per earlier placement under each pixel a new placement changes (avoids muddy stacking)");
+        parser.push(&mut distance_exponent, None, "distance-exponent", "exponent of the squared color distance per pixel (higher punishes big errors more)");
+        parser.push_flag(&mut shadow, None, "shadow", "draw a drop shadow behind each little image", true);
+        parser.push(&mut shadow_offset, None, "shadow-offset", "offset of the drop shadow in pixels");
+        parser.push(&mut shadow_darkness, None, "shadow-darkness", "darkness of the drop shadow (0 to 1)");
+        parser.push_flag(&mut stroke, None, "stroke", "draw an outline around each little image", true);
+        parser.push(&mut stroke_width, None, "stroke-width", "width of the outline in pixels");
+        parser.push(&mut stroke_color, None, "stroke-color", "hex rgb color of the outline (default white)");
+        parser.push(&mut vignette, None, "vignette", "how much to darken the corners of the finished collage (0 to 1)");
+        parser.push_flag(&mut die_cut, None, "die-cut", "make the output transparent wherever no little image covers it", true);
+        parser.push(&mut edge_mode, None, "edge-mode", format!("how to sample outside of the little images, options: {}", EdgeMode::list_all()));
+        parser.push(&mut rotation_sampling, None, "rotation-sampling", format!("how rotated little images r sampled, bilinear has smoother edges, options: {}", RotationSampling::list_all()));
+        parser.push(&mut scale_filter, None, "scale-filter", format!("filter used when scaling the little images, options: {}", ScaleFilter::list_all()));
+        parser.push_flag(&mut single_placement, None, "single-placement", "only find the best placement of a single little image and print it", true);
+        parser.push_flag(&mut stamp, None, "stamp", "use every little image exactly once (ignores amount)", true);
+        parser.push(&mut voronoi_cells, None, "voronoi-cells", "fill this many voronoi cells with the closest colored little images instead of annealing");
+        parser.push(&mut voronoi_jitter, None, "voronoi-jitter", "how far the voronoi seeds stray from a grid (0 to 1)");
+        parser.push_flag(&mut energy_report, None, "energy-report", "print how much each placed image lowered the error after finishing", true);
+        parser.push_flag(&mut stats, None, "stats", "also print the median and 95th percentile error per pixel", true);
         parser.push_flag(&mut debug, None, "debug", "debug mode", true);
+        parser.push(&mut debug_every, None, "debug-every", "only save a debug image every this many placements");
+        parser.push_flag(&mut debug_boxes, None, "debug-boxes", "draw where the last little image went on each debug image", true);
 
         if let Err(err) = parser.parse(args)
         {
@@ -672,20 +1215,111 @@ impl Config
         }
 
         Self{
-            directory: directory.unwrap(),
+            directory,
+            image_list,
+            images,
+            frames,
+            frame_stride,
+            lazy_cache,
+            max_memory,
             input: input.unwrap(),
             output,
+            quality,
+            resume,
+            resume_placed,
+            checkpoint,
+            load_checkpoint,
+            seed,
             max_size,
+            max_width,
+            max_height,
             little_size,
+            match_histogram,
+            tile_shape,
+            color_space,
+            white_point,
+            resize_filter,
+            linear_resize,
             steps,
+            background_steps,
+            background_starts,
             amount,
+            auto_amount,
             starts,
+            greedy_candidates,
+            tabu_size,
+            neighbor_batch,
+            commit,
+            optimizer,
             starting_temperature,
+            temperature_floor,
+            acceptance_gamma,
+            strict_acceptance,
+            patience,
+            patience_epsilon,
+            cooling_cycles,
+            flat_background,
+            background_color,
+            background,
+            background_init,
+            background_cells,
+            warm_start,
+            margin,
+            threads,
+            tile_size,
+            tile_overlap,
+            energy_downscale,
+            energy_stride,
             allow_hue,
+            allow_blend_modes,
             allow_transparency,
+            transparency_lower_bound,
+            transparency_mode,
+            min_alpha,
+            max_tile_opacity,
             allow_rotation,
+            angle_mode,
+            angle_steps,
+            no_rotate_list,
             allow_scaling,
-            debug
+            allow_nonuniform_scaling,
+            scale_min,
+            scale_max,
+            scale_decay,
+            min_placement_pixels,
+            scaling_unlock,
+            rotation_unlock,
+            hue_unlock,
+            transparency_unlock,
+            color_weight,
+            gradient_weight,
+            ssim_weight,
+            segment_weight,
+            segment_step,
+            grid_cap,
+            grid_cells,
+            overlap_weight,
+            distance_exponent,
+            shadow,
+            shadow_offset,
+            shadow_darkness,
+            stroke,
+            stroke_width,
+            stroke_color,
+            vignette,
+            die_cut,
+            edge_mode,
+            rotation_sampling,
+            scale_filter,
+            single_placement,
+            stamp,
+            voronoi_cells,
+            voronoi_jitter,
+            energy_report,
+            stats,
+            debug,
+            debug_every,
+            debug_boxes
         }
     }
 }
@@ -0,0 +1,156 @@
+use std::{
+    fs,
+    path::{Path, PathBuf}
+};
+
+
+// * matches any run of characters and ? matches exactly one, both within a single name
+fn name_matches(pattern: &[char], name: &[char]) -> bool
+{
+    match pattern.split_first()
+    {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| name_matches(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && name_matches(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && name_matches(rest, &name[1..])
+    }
+}
+
+fn has_wildcard(part: &str) -> bool
+{
+    part.contains(['*', '?'])
+}
+
+// ** matches any amount of directories (including none)
+pub fn glob(pattern: &str) -> Result<Vec<PathBuf>, String>
+{
+    let mut base = PathBuf::new();
+    let mut parts: Vec<String> = Vec::new();
+
+    for component in Path::new(pattern).components()
+    {
+        let part = component.as_os_str().to_str()
+            .ok_or_else(|| format!("pattern isnt valid unicode: {pattern}"))?;
+
+        if part.contains("**") && part != "**"
+        {
+            return Err(format!("invalid pattern {pattern}: ** has to be a whole path component"));
+        }
+
+        if parts.is_empty() && !has_wildcard(part)
+        {
+            base.push(component);
+        } else
+        {
+            parts.push(part.to_owned());
+        }
+    }
+
+    if parts.is_empty()
+    {
+        return Ok(base.is_file().then_some(base).into_iter().collect());
+    }
+
+    if base.as_os_str().is_empty()
+    {
+        base = PathBuf::from(".");
+    }
+
+    let mut found = Vec::new();
+    walk(&base, &parts, &mut found);
+
+    found.sort();
+
+    Ok(found)
+}
+
+fn walk(directory: &Path, parts: &[String], found: &mut Vec<PathBuf>)
+{
+    let Some((part, rest)) = parts.split_first() else { return };
+
+    let Ok(entries) = fs::read_dir(directory) else { return };
+    let entries: Vec<fs::DirEntry> = entries.filter_map(Result::ok).collect();
+
+    if part == "**"
+    {
+        walk(directory, rest, found);
+
+        // file_type doesnt follow symlinks, so a link back up the tree cant loop forever
+        entries.iter().filter(|entry|
+        {
+            entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false)
+        }).for_each(|entry|
+        {
+            walk(&entry.path(), parts, found);
+        });
+
+        return;
+    }
+
+    let pattern: Vec<char> = part.chars().collect();
+
+    entries.into_iter().map(|entry| entry.path()).filter(|path|
+    {
+        path.file_name().and_then(|name| name.to_str()).map(|name|
+        {
+            name_matches(&pattern, &name.chars().collect::<Vec<_>>())
+        }).unwrap_or(false)
+    }).for_each(|path|
+    {
+        if rest.is_empty()
+        {
+            if path.is_file()
+            {
+                found.push(path);
+            }
+        } else if path.is_dir()
+        {
+            walk(&path, rest, found);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool
+    {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+
+        name_matches(&pattern, &name)
+    }
+
+    #[test]
+    fn wildcards()
+    {
+        assert!(matches("*.jpg", "cat.jpg"));
+        assert!(matches("*.jpg", ".jpg"));
+        assert!(!matches("*.jpg", "cat.jpeg"));
+        assert!(matches("c?t*", "cat.png"));
+        assert!(!matches("c?t", "ct"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn doesnt_follow_symlinked_directories()
+    {
+        let root = std::env::temp_dir().join(format!("annealingcollager_glob_{}", std::process::id()));
+        let inner = root.join("inner");
+
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(inner.join("a.png"), []).unwrap();
+
+        // a loop back to the root, following it would never end
+        std::os::unix::fs::symlink(&root, inner.join("loop")).unwrap();
+
+        let found = glob(&format!("{}/**/*.png", root.display())).unwrap();
+
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(found, vec![inner.join("a.png")]);
+    }
+}
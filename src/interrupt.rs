@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+const SIGINT: i32 = 2;
+const SIG_DFL: usize = 0;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C"
+{
+    fn signal(signum: i32, handler: usize) -> usize;
+}
+
+extern "C" fn on_interrupt(_signum: i32)
+{
+    INTERRUPTED.store(true, Ordering::Relaxed);
+
+    // the second ctrl-c kills the process like normal
+    unsafe{ signal(SIGINT, SIG_DFL); }
+}
+
+pub fn install() -> &'static AtomicBool
+{
+    unsafe{ signal(SIGINT, on_interrupt as extern "C" fn(i32) as usize); }
+
+    &INTERRUPTED
+}
@@ -1,14 +1,35 @@
-use std::ops::{Index, IndexMut};
+use std::{
+    thread,
+    io::Write,
+    collections::VecDeque,
+    f32::consts,
+    num::NonZeroUsize,
+    ops::{Deref, Index, IndexMut}
+};
 
 use image::{
-    Rgb,
+    Pixel,
+    ImageBuffer,
+    ImageError,
+    ImageResult,
+    DynamicImage,
     RgbImage,
+    RgbaImage,
     Rgb32FImage,
     Rgba32FImage,
     buffer::ConvertBuffer
 };
 
-use crate::{Lab, Laba, Point2};
+use crate::{
+    Lab,
+    Laba,
+    Point2,
+    EdgeMode,
+    RotationSampling,
+    ScaleFilter,
+    TileShape,
+    colors::{self, ColorConversion, Blending}
+};
 
 
 #[derive(Debug, Clone, Copy)]
@@ -35,6 +56,40 @@ impl Indexer
     }
 }
 
+// bounding box of the touched pixels, high is exclusive
+#[derive(Debug, Clone, Copy)]
+struct TouchedBounds(Option<(Point2<i32>, Point2<i32>)>);
+
+impl TouchedBounds
+{
+    pub fn new() -> Self
+    {
+        Self(None)
+    }
+
+    pub fn touch(&mut self, position: Point2<i32>)
+    {
+        let next = position + 1;
+
+        self.0 = Some(match self.0
+        {
+            Some((low, high)) =>
+            {
+                let low = low.zip(position).map(|(a, b)| a.min(b));
+                let high = high.zip(next).map(|(a, b)| a.max(b));
+
+                (low, high)
+            },
+            None => (position, next)
+        });
+    }
+
+    pub fn bounds(self) -> (Point2<i32>, Point2<i32>)
+    {
+        self.0.unwrap_or((Point2::repeat(0), Point2::repeat(0)))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GenericImage<T>
 {
@@ -73,6 +128,16 @@ impl<T> GenericImage<T>
         Self{data: vec![pixel; width * height], indexer: Indexer::new(width, height)}
     }
 
+    pub fn cropped(&self, low: Point2<i32>, size: Point2<usize>) -> Self
+    where
+        T: Clone
+    {
+        Self::from_fn(size.x, size.y, |position|
+        {
+            self[low + position].clone()
+        })
+    }
+
     pub fn map<F, U>(self, f: F) -> GenericImage<U>
     where
         F: FnMut(T) -> U
@@ -83,6 +148,61 @@ impl<T> GenericImage<T>
         }
     }
 
+    // pixels row by row, width * height of them
+    pub fn as_raw(&self) -> &[T]
+    {
+        &self.data
+    }
+
+    pub fn into_raw(self) -> Vec<T>
+    {
+        self.data
+    }
+
+    // works with any pixel type of the image crate, f turns each pixel into T
+    pub fn from_image_buffer<P, C, F>(buffer: &ImageBuffer<P, C>, mut f: F) -> Self
+    where
+        P: Pixel,
+        C: Deref<Target=[P::Subpixel]>,
+        F: FnMut(&P) -> T
+    {
+        let data = buffer.pixels().map(&mut f).collect();
+
+        Self::from_raw(data, buffer.width() as usize, buffer.height() as usize)
+    }
+
+    pub fn to_image_buffer<P, F>(&self, mut f: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where
+        P: Pixel,
+        F: FnMut(&T) -> P
+    {
+        let mut pixels = self.data.iter();
+
+        ImageBuffer::from_fn(self.width() as u32, self.height() as u32, |_, _|
+        {
+            f(pixels.next().unwrap())
+        })
+    }
+
+    // anything outside the image just doesnt get drawn
+    pub fn draw_line(&mut self, from: Point2<f32>, to: Point2<f32>, value: T)
+    where
+        T: Clone
+    {
+        let difference = to - from;
+        let steps = difference.x.abs().max(difference.y.abs()).ceil().max(1.0) as usize;
+
+        (0..=steps).for_each(|step|
+        {
+            let position = from + difference * (step as f32 / steps as f32);
+
+            if let Some(pixel) = self.get_mut(position.map(|x| x.round() as i32))
+            {
+                *pixel = value.clone();
+            }
+        });
+    }
+
     pub fn width(&self) -> usize
     {
         self.indexer.0.x
@@ -93,7 +213,7 @@ impl<T> GenericImage<T>
         self.indexer.0.y
     }
 
-    pub fn pixels(&self) -> impl Iterator<Item=&T>
+    pub fn pixels(&self) -> impl ExactSizeIterator<Item=&T>
     {
         self.data.iter()
     }
@@ -121,6 +241,18 @@ impl<T> GenericImage<T>
         })
     }
 
+    pub fn pixels_between(
+        &self,
+        low: Point2<i32>,
+        high: Point2<i32>
+    ) -> impl Iterator<Item=(Point2<i32>, &T)>
+    {
+        self.pixels_positions().filter(move |(position, _x)|
+        {
+            Self::between(low, high, *position)
+        })
+    }
+
     pub fn pixels_between_mut(
         &mut self,
         low: Point2<i32>,
@@ -153,10 +285,27 @@ impl<T> GenericImage<T>
         })
     }
 
+    pub fn get_edge(&self, position: Point2<i32>, edge: EdgeMode) -> Option<&T>
+    {
+        let size = self.size_point().map(|x| x as i32);
+
+        let position = match edge
+        {
+            EdgeMode::Transparent => position,
+            EdgeMode::Clamp => position.zip(size).map(|(x, limit)| x.clamp(0, (limit - 1).max(0))),
+            EdgeMode::Wrap => position.zip(size).map(|(x, limit)| x.rem_euclid(limit.max(1)))
+        };
+
+        self.get(position)
+    }
+
+    // sizes below 1 pixel would divide by zero, so they always get at least 1
     pub fn resized_nearest(&self, size: Point2<usize>) -> Self
     where
         T: Clone
     {
+        let size = size.map(|x| x.max(1));
+
         let this_size = self.size_point();
         let scale = this_size.map(|x| x as f32) / size.map(|x| x as f32);
 
@@ -173,6 +322,36 @@ impl<T> GenericImage<T>
         })
     }
 
+    // each turn is 90 degrees in the same direction as overlay_rotated
+    pub fn rotated_quarters(&self, turns: u32) -> Self
+    where
+        T: Clone
+    {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+
+        let turns = turns % 4;
+        let size = if turns.is_multiple_of(2)
+        {
+            self.size_point()
+        } else
+        {
+            Point2{x: self.height(), y: self.width()}
+        };
+
+        Self::from_fn(size.x, size.y, |Point2{x, y}|
+        {
+            let position = match turns
+            {
+                0 => Point2{x, y},
+                1 => Point2{x: width - 1 - y, y: x},
+                2 => Point2{x: width - 1 - x, y: height - 1 - y},
+                _ => Point2{x: y, y: height - 1 - x}
+            };
+
+            self[position].clone()
+        })
+    }
+
     pub fn size_point(&self) -> Point2<usize>
     {
         self.indexer.0
@@ -194,190 +373,1665 @@ impl<T> GenericImage<T>
     }
 }
 
-impl<T> Index<Point2<i32>> for GenericImage<T>
+pub trait Resamplable: Copy
 {
-    type Output = T;
+    fn zero() -> Self;
+    fn add_scaled(self, other: Self, scale: f32) -> Self;
 
-    fn index(&self, index: Point2<i32>) -> &Self::Output
+    // for clamping values that cubic kernels can overshoot
+    fn normalized(self) -> Self
     {
-        self.get(index).unwrap()
+        self
     }
 }
 
-impl<T> IndexMut<Point2<i32>> for GenericImage<T>
+impl Resamplable for f32
 {
-    fn index_mut(&mut self, index: Point2<i32>) -> &mut Self::Output
+    fn zero() -> Self
     {
-        self.get_mut(index).unwrap()
+        0.0
     }
-}
 
-pub type LabaImage = GenericImage<Laba>;
+    fn add_scaled(self, other: Self, scale: f32) -> Self
+    {
+        self + other * scale
+    }
+}
 
-impl LabaImage
+impl Resamplable for Lab
 {
-    pub fn to_rgb(self) -> RgbImage
+    fn zero() -> Self
     {
-        LabImage::from(self).to_rgb()
+        Lab{l: 0.0, a: 0.0, b: 0.0}
     }
 
-    pub fn overlay(mut self, other: &LabaImage, position: Point2<i32>) -> LabaImage
+    fn add_scaled(self, other: Self, scale: f32) -> Self
     {
-        other.pixels_positions().for_each(|(pixel_position, pixel)|
-        {
-            let position = position + pixel_position;
-            if let Some(this_pixel) = self.get_mut(position)
-            {
-                *this_pixel = this_pixel.blend(*pixel);
-            }
-        });
-
-        self
+        Lab{
+            l: self.l + other.l * scale,
+            a: self.a + other.a * scale,
+            b: self.b + other.b * scale
+        }
     }
 }
 
-impl From<Rgba32FImage> for LabaImage
+impl Resamplable for Laba
 {
-    fn from(value: Rgba32FImage) -> Self
+    fn zero() -> Self
     {
-        Self::from(&value)
+        Laba{l: 0.0, a: 0.0, b: 0.0, alpha: 0.0}
     }
-}
 
-impl From<&Rgba32FImage> for LabaImage
-{
-    fn from(value: &Rgba32FImage) -> Self
+    fn add_scaled(self, other: Self, scale: f32) -> Self
     {
-        let data = value.pixels().map(|pixel|
-        {
-            Laba::from(*pixel)
-        }).collect();
+        Laba{
+            l: self.l + other.l * scale,
+            a: self.a + other.a * scale,
+            b: self.b + other.b * scale,
+            alpha: self.alpha + other.alpha * scale
+        }
+    }
 
-        GenericImage::from_raw(data, value.width() as usize, value.height() as usize)
+    fn normalized(self) -> Self
+    {
+        Laba{alpha: self.alpha.clamp(0.0, 1.0), ..self}
     }
 }
 
-pub type LabImage = GenericImage<Lab>;
-
-impl LabImage
+impl<T: Resamplable> GenericImage<T>
 {
-    pub fn to_rgb(self) -> RgbImage
+    // averages blocks of factor by factor pixels, with the block grid starting shift pixels
+    // before the image so it can line up with something offset inside of it
+    pub fn box_downscaled(&self, factor: usize, shift: usize) -> Self
     {
-        RgbImage::from_raw(
-            self.width() as u32,
-            self.height() as u32,
-            self.pixels().flat_map(|pixel|
-            {
-                let rgb = Rgb::from(*pixel);
+        let size = self.size_point().map(|x| (x + shift).div_ceil(factor));
 
-                rgb.0
-            }).collect()
-        ).unwrap()
-    }
+        let mut output = Self::repeat(T::zero(), size.x, size.y);
 
-    pub fn overlay(mut self, other: &LabaImage, position: Point2<i32>) -> LabImage
-    {
-        other.pixels_positions().for_each(|(pixel_position, pixel)|
-        {
-            let position = position + pixel_position;
-            if let Some(this_pixel) = self.get_mut(position)
-            {
-                *this_pixel = this_pixel.blend(*pixel);
-            }
-        });
+        let high = self.size_point().map(|x| x as i32);
+        output.update_box_downscaled(self, factor, shift, Point2::repeat(0), high);
 
-        self
+        output
     }
 
-    pub fn overlay_rotated(
-        mut self,
-        other: &LabaImage,
-        position: Point2<i32>,
-        angle: f32
-    ) -> LabImage
+    // recomputes only the blocks overlapping the full resolution area from low to high
+    pub fn update_box_downscaled(
+        &mut self,
+        full: &Self,
+        factor: usize,
+        shift: usize,
+        low: Point2<i32>,
+        high: Point2<i32>
+    )
     {
-        let rotate = |origin: Point2<f32>, position: Point2<i32>, angle: f32|
+        let factor = factor as i32;
+        let shift = shift as i32;
+
+        let full_size = full.size_point().map(|x| x as i32);
+        let size = self.size_point().map(|x| x as i32);
+
+        let block_low = low.map(|x| ((x + shift).div_euclid(factor)).max(0));
+        let block_high = (high - 1).zip(size).map(|(x, size)|
         {
-            let position = position.map(|x| x as f32) - origin;
+            ((x + shift).div_euclid(factor) + 1).min(size)
+        });
 
-            let a_cos = angle.cos();
-            let a_sin = angle.sin();
+        for y in block_low.y..block_high.y
+        {
+            for x in block_low.x..block_high.x
+            {
+                let start = (Point2{x, y} * factor - shift).map(|x| x.max(0));
+                let end = (Point2{x, y} * factor - shift + factor).zip(full_size)
+                    .map(|(x, size)| x.min(size));
 
-            Point2{
-                x: a_cos * position.x - a_sin * position.y,
-                y: a_sin * position.x + a_cos * position.y
-            } + origin
-        };
+                let mut sum = T::zero();
+                let mut count = 0;
 
-        let middle = other.size_point().map(|x| x as f32) / 2.0;
+                for full_y in start.y..end.y
+                {
+                    for full_x in start.x..end.x
+                    {
+                        sum = sum.add_scaled(full[Point2{x: full_x, y: full_y}], 1.0);
+                        count += 1;
+                    }
+                }
+
+                if count > 0
+                {
+                    self[Point2{x, y}] = T::zero().add_scaled(sum, 1.0 / count as f32);
+                }
+            }
+        }
+    }
 
-        let global_middle = position.map(|x| x as f32) + middle;
+    pub fn resized(&self, size: Point2<usize>, filter: ScaleFilter) -> Self
+    {
+        match filter
+        {
+            ScaleFilter::Nearest => self.resized_nearest(size),
+            ScaleFilter::Bilinear => self.resized_bilinear(size),
+            ScaleFilter::Bicubic => self.resized_bicubic(size)
+        }
+    }
 
-        let this_rotate = |position: Point2<i32>|
+    pub fn resized_bilinear(&self, size: Point2<usize>) -> Self
+    {
+        self.resized_with(size, 0, |t|
         {
-            rotate(global_middle, position, angle)
-        };
+            [1.0 - t, t]
+        })
+    }
 
-        let size = other.size_point().map(|x| x as i32);
+    // catmull-rom kernel
+    pub fn resized_bicubic(&self, size: Point2<usize>) -> Self
+    {
+        self.resized_with(size, -1, |t|
+        {
+            let t2 = t * t;
+            let t3 = t2 * t;
+
+            [
+                (-t3 + 2.0 * t2 - t) / 2.0,
+                (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+                (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+                (t3 - t2) / 2.0
+            ]
+        })
+    }
 
-        let rotated_ll = this_rotate(position);
-        let rotated_lh = this_rotate(position + Point2{x: 0, ..size});
-        let rotated_hl = this_rotate(position + Point2{y: 0, ..size});
-        let rotated_hh = this_rotate(position + size);
+    fn resized_with<F, const N: usize>(
+        &self,
+        size: Point2<usize>,
+        first_tap: i32,
+        weights: F
+    ) -> Self
+    where
+        F: Fn(f32) -> [f32; N]
+    {
+        let size = size.map(|x| x.max(1));
 
-        let rotated = rotated_ll.zip(rotated_lh).zip(rotated_hl).zip(rotated_hh);
+        let scale = self.size_point().map(|x| x as f32) / size.map(|x| x as f32);
 
-        fn select<F>(f: F) -> impl FnMut((((f32, f32), f32), f32)) -> f32
-        where
-            F: Fn(f32, f32) -> f32
+        Self::from_fn(size.x, size.y, |position|
         {
-            move |(((ll, lh), hl), hh)| f(ll, f(lh, f(hl, hh)))
-        }
+            // sample at pixel centers so both edges line up
+            let source = (position.map(|x| x as f32) + 0.5) * scale - 0.5;
 
-        // bounding boxes
-        let bb_low = rotated.map(select(f32::min)).map(|x| x.floor() as i32);
-        let bb_high = rotated.map(select(f32::max)).map(|x| x.ceil() as i32);
+            let start = source.map(|x| x.floor());
+            let weight = source.zip(start).map(|(x, start)| weights(x - start));
 
-        self.pixels_between_mut(bb_low, bb_high).for_each(|(pixel_position, pixel)|
-        {
-            let position = rotate(global_middle, pixel_position, angle)
-                .map(|x| x.round() as i32) - position;
+            let start = start.map(|x| x as i32 + first_tap);
 
-            if let Some(other_pixel) = other.get(position)
+            let mut value = T::zero();
+            for y in 0..N
             {
-                *pixel = pixel.blend(*other_pixel);
+                for x in 0..N
+                {
+                    let offset = Point2{x: x as i32, y: y as i32};
+                    let pixel = *self.get_edge(start + offset, EdgeMode::Clamp).unwrap();
+
+                    value = value.add_scaled(pixel, weight.x[x] * weight.y[y]);
+                }
             }
-        });
 
-        self
+            value.normalized()
+        })
     }
 }
 
-impl From<LabaImage> for LabImage
+impl<T> Index<Point2<i32>> for GenericImage<T>
 {
-    fn from(value: LabaImage) -> Self
+    type Output = T;
+
+    fn index(&self, index: Point2<i32>) -> &Self::Output
     {
-        value.map(|pixel| pixel.no_alpha())
+        self.get(index).unwrap()
     }
 }
 
-impl From<RgbImage> for LabImage
+impl<T> IndexMut<Point2<i32>> for GenericImage<T>
 {
-    fn from(value: RgbImage) -> Self
+    fn index_mut(&mut self, index: Point2<i32>) -> &mut Self::Output
     {
-        <Self as From<Rgb32FImage>>::from(value.convert())
+        self.get_mut(index).unwrap()
     }
 }
 
-impl From<Rgb32FImage> for LabImage
+#[derive(Debug, Clone, Copy)]
+pub struct Shadow
 {
-    fn from(value: Rgb32FImage) -> Self
+    pub offset: Point2<i32>,
+    pub darkness: f32
+}
+
+impl Shadow
+{
+    // only the alpha shape of the image is kept, the colors r all black
+    pub fn silhouette(&self, image: &LabaImage) -> LabaImage
     {
-        let data = value.pixels().map(|pixel|
+        image.clone().map(|pixel|
         {
-            Lab::from(*pixel)
-        }).collect();
+            Laba{l: 0.0, a: 0.0, b: 0.0, alpha: pixel.alpha * self.darkness}
+        })
+    }
+}
 
-        GenericImage::from_raw(data, value.width() as usize, value.height() as usize)
+#[derive(Debug, Clone, Copy)]
+pub struct Stroke
+{
+    pub width: u32,
+    pub color: Lab
+}
+
+impl Stroke
+{
+    // the image grown by width on every side in the stroke color, its drawn under the image
+    // so only the part sticking out shows
+    pub fn outline(&self, image: &LabaImage) -> LabaImage
+    {
+        let width = self.width as i32;
+        let size = image.size_point().map(|x| x as i32);
+
+        // a square max in 2 passes, rounded corners would need width^2 samples per pixel
+        let grown = |alpha: &GradientImage, along: Point2<i32>, size: Point2<usize>|
+        {
+            GradientImage::from_fn(size.x, size.y, |position|
+            {
+                (-width..=width).filter_map(|offset|
+                {
+                    alpha.get(position + along * (offset - width)).copied()
+                }).fold(0.0, f32::max)
+            })
+        };
+
+        let alpha = image.clone().map(|pixel| pixel.alpha);
+
+        let padded = (size + width * 2).map(|x| x as usize);
+
+        let rows = grown(&alpha, Point2{x: 1, y: 0}, Point2{x: padded.x, y: size.y as usize});
+        let alpha = grown(&rows, Point2{x: 0, y: 1}, padded);
+
+        alpha.map(|alpha| Laba{alpha, ..Laba::from(self.color)})
+    }
+}
+
+pub type LabaImage = GenericImage<Laba>;
+
+impl LabaImage
+{
+    // rotates around the middle into an image big enough to fit all of it, the offset is where
+    // its top left ends up compared to the unrotated one, same pixels as overlay_rotated
+    pub fn rotated(&self, angle: f32, edge: EdgeMode, sampling: RotationSampling) -> (Self, Point2<i32>)
+    {
+        let quarters = angle / consts::FRAC_PI_2;
+        let turns = quarters.round();
+
+        if (quarters - turns).abs() < 0.0001
+        {
+            let rotated = self.rotated_quarters(turns.rem_euclid(4.0) as u32);
+
+            let size_difference = self.size_point().map(|x| x as f32)
+                - rotated.size_point().map(|x| x as f32);
+
+            return (rotated, (size_difference / 2.0).map(|x| x.round() as i32));
+        }
+
+        let middle = self.size_point().map(|x| x as f32) / 2.0;
+        let (a_sin, a_cos) = angle.sin_cos();
+
+        let rotate = |position: Point2<f32>|
+        {
+            let position = position - middle;
+
+            Point2{
+                x: a_cos * position.x - a_sin * position.y,
+                y: a_sin * position.x + a_cos * position.y
+            } + middle
+        };
+
+        let size = self.size_point().map(|x| x as f32);
+        let corners = [
+            Point2{x: 0.0, y: 0.0},
+            Point2{x: 0.0, y: size.y},
+            Point2{x: size.x, y: 0.0},
+            size
+        ].map(rotate);
+
+        let low = corners.iter().copied()
+            .reduce(|a, b| a.zip(b).map(|(a, b)| a.min(b)))
+            .unwrap()
+            .map(|x| x.floor() as i32);
+
+        let high = corners.iter().copied()
+            .reduce(|a, b| a.zip(b).map(|(a, b)| a.max(b)))
+            .unwrap()
+            .map(|x| x.ceil() as i32);
+
+        let rotated_size = (high - low).map(|x| x.max(1) as usize);
+
+        let rotated = Self::from_fn(rotated_size.x, rotated_size.y, |position|
+        {
+            let position = rotate((position + low).map(|x| x as f32));
+
+            let pixel = match sampling
+            {
+                RotationSampling::Nearest =>
+                {
+                    self.get_edge(position.map(|x| x.round() as i32), edge).copied()
+                },
+                RotationSampling::Bilinear => self.sample_bilinear(position, edge)
+            };
+
+            pixel.unwrap_or(Laba{l: 0.0, a: 0.0, b: 0.0, alpha: 0.0})
+        });
+
+        (rotated, low)
+    }
+
+    // blends the 4 pixels around the position weighted by their alpha so transparent ones
+    // dont bleed their color in, pixels outside of the image count as fully transparent
+    pub fn sample_bilinear(&self, position: Point2<f32>, edge: EdgeMode) -> Option<Laba>
+    {
+        let low = position.map(|x| x.floor());
+        let fraction = position - low;
+        let low = low.map(|x| x as i32);
+
+        let mut total = Laba{l: 0.0, a: 0.0, b: 0.0, alpha: 0.0};
+        let mut found = false;
+
+        [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().for_each(|(x, y)|
+        {
+            let weight_x = if x == 0 { 1.0 - fraction.x } else { fraction.x };
+            let weight_y = if y == 0 { 1.0 - fraction.y } else { fraction.y };
+
+            let weight = weight_x * weight_y;
+
+            if weight <= 0.0
+            {
+                return;
+            }
+
+            if let Some(pixel) = self.get_edge(low + Point2{x, y}, edge)
+            {
+                let scale = weight * pixel.alpha;
+
+                total.l += pixel.l * scale;
+                total.a += pixel.a * scale;
+                total.b += pixel.b * scale;
+                total.alpha += scale;
+
+                found = true;
+            }
+        });
+
+        if !found
+        {
+            return None;
+        }
+
+        if total.alpha > 0.0
+        {
+            total.l /= total.alpha;
+            total.a /= total.alpha;
+            total.b /= total.alpha;
+        }
+
+        Some(total)
+    }
+
+    // moves every channel so its distribution matches the histogram, transparent pixels dont count
+    pub fn histogram_matched(mut self, histogram: &Histogram) -> Self
+    {
+        let visible: Vec<usize> = self.data.iter().enumerate()
+            .filter(|(_, pixel)| pixel.alpha > 0.0)
+            .map(|(index, _)| index)
+            .collect();
+
+        if visible.is_empty() || histogram.quantiles[0].is_empty()
+        {
+            return self;
+        }
+
+        let last = (visible.len() - 1).max(1) as f32;
+        for channel in 0..3
+        {
+            let value = |pixel: &Laba| Histogram::channel(pixel.no_alpha(), channel);
+
+            let mut order = visible.clone();
+            order.sort_unstable_by(|a, b| value(&self.data[*a]).total_cmp(&value(&self.data[*b])));
+
+            order.into_iter().enumerate().for_each(|(rank, index)|
+            {
+                let matched = histogram.value_at(channel, rank as f32 / last);
+
+                let pixel = &mut self.data[index];
+                match channel
+                {
+                    0 => pixel.l = matched,
+                    1 => pixel.a = matched,
+                    _ => pixel.b = matched
+                }
+            });
+        }
+
+        self
+    }
+
+    pub fn to_rgb(self) -> RgbImage
+    {
+        LabImage::from(self).to_rgb()
+    }
+
+    // makes everything outside the shape inscribed in the bounds fully transparent
+    pub fn masked(mut self, shape: TileShape) -> Self
+    {
+        let half = self.size_point().map(|x| x as f32 / 2.0);
+
+        let radius = match shape
+        {
+            TileShape::Rect => return self,
+            TileShape::Circle => Point2{x: half.x.min(half.y), y: half.x.min(half.y)},
+            TileShape::Ellipse => half
+        };
+
+        self.pixels_positions_mut().for_each(|(position, pixel)|
+        {
+            let x = (position.x as f32 + 0.5 - half.x) / radius.x;
+            let y = (position.y as f32 + 0.5 - half.y) / radius.y;
+
+            if x * x + y * y > 1.0
+            {
+                pixel.alpha = 0.0;
+            }
+        });
+
+        self
+    }
+
+    pub fn overlay(
+        mut self,
+        other: &LabaImage,
+        position: Point2<i32>
+    ) -> (LabaImage, Point2<i32>, Point2<i32>)
+    {
+        let mut touched = TouchedBounds::new();
+
+        other.pixels_positions().for_each(|(pixel_position, pixel)|
+        {
+            let position = position + pixel_position;
+            if let Some(this_pixel) = self.get_mut(position)
+            {
+                *this_pixel = this_pixel.blend(*pixel);
+
+                touched.touch(position);
+            }
+        });
+
+        let (low, high) = touched.bounds();
+
+        (self, low, high)
+    }
+}
+
+impl From<Rgba32FImage> for LabaImage
+{
+    fn from(value: Rgba32FImage) -> Self
+    {
+        Self::from(&value)
+    }
+}
+
+impl From<&Rgba32FImage> for LabaImage
+{
+    fn from(value: &Rgba32FImage) -> Self
+    {
+        Self::from_rgba_in(value, ColorConversion::default())
+    }
+}
+
+impl LabaImage
+{
+    pub fn from_rgba_in(value: &Rgba32FImage, conversion: ColorConversion) -> Self
+    {
+        let data = value.pixels().map(|pixel|
+        {
+            conversion.laba_from_rgba(*pixel)
+        }).collect();
+
+        GenericImage::from_raw(data, value.width() as usize, value.height() as usize)
+    }
+}
+
+pub type GradientImage = GenericImage<f32>;
+
+// summed area table, each entry is the sum of everything above and left of it
+#[derive(Debug, Clone)]
+pub struct IntegralImage(GenericImage<[f64; 3]>);
+
+impl IntegralImage
+{
+    pub fn new(image: &LabImage) -> Self
+    {
+        let size = image.size_point();
+
+        let mut sums = GenericImage::repeat([0.0; 3], size.x + 1, size.y + 1);
+
+        for y in 1..=size.y as i32
+        {
+            for x in 1..=size.x as i32
+            {
+                let pixel = image[Point2{x: x - 1, y: y - 1}];
+                let pixel = [pixel.l, pixel.a, pixel.b];
+
+                let up = sums[Point2{x, y: y - 1}];
+                let left = sums[Point2{x: x - 1, y}];
+                let diagonal = sums[Point2{x: x - 1, y: y - 1}];
+
+                sums[Point2{x, y}] = [0, 1, 2].map(|i|
+                {
+                    pixel[i] as f64 + up[i] + left[i] - diagonal[i]
+                });
+            }
+        }
+
+        Self(sums)
+    }
+
+    // high is exclusive, the region gets clamped to the image
+    pub fn region_mean(&self, low: Point2<i32>, high: Point2<i32>) -> Option<Lab>
+    {
+        let limit = self.0.size_point().map(|x| x as i32 - 1);
+
+        let clamp = |point: Point2<i32>|
+        {
+            point.zip(limit).map(|(x, limit)| x.clamp(0, limit))
+        };
+
+        let (low, high) = (clamp(low), clamp(high));
+
+        if high.x <= low.x || high.y <= low.y
+        {
+            return None;
+        }
+
+        let area = ((high.x - low.x) * (high.y - low.y)) as f64;
+
+        let at = |x, y| self.0[Point2{x, y}];
+
+        let (hh, lh, hl, ll) = (at(high.x, high.y), at(low.x, high.y), at(high.x, low.y), at(low.x, low.y));
+
+        let [l, a, b] = [0, 1, 2].map(|i|
+        {
+            ((hh[i] - lh[i] - hl[i] + ll[i]) / area) as f32
+        });
+
+        Some(Lab{l, a, b})
+    }
+}
+
+// connected areas of pixels that fall into the same color bucket
+#[derive(Debug, Clone)]
+pub struct Segments
+{
+    regions: GenericImage<usize>,
+    means: Vec<Lab>
+}
+
+impl Segments
+{
+    pub fn new(image: &LabImage, step: f32) -> Self
+    {
+        let bucket = |pixel: Lab|
+        {
+            [pixel.l, pixel.a, pixel.b].map(|x| (x / step).floor() as i32)
+        };
+
+        let size = image.size_point();
+        let mut regions = GenericImage::repeat(usize::MAX, size.x, size.y);
+        let mut means = Vec::new();
+
+        for y in 0..size.y as i32
+        {
+            for x in 0..size.x as i32
+            {
+                let start = Point2{x, y};
+
+                if regions[start] != usize::MAX
+                {
+                    continue;
+                }
+
+                let region = means.len();
+                let color = bucket(image[start]);
+
+                let mut sum = Lab::zero();
+                let mut count = 0;
+
+                regions[start] = region;
+
+                let mut queue = VecDeque::from([start]);
+                while let Some(position) = queue.pop_front()
+                {
+                    sum = sum.add_scaled(image[position], 1.0);
+                    count += 1;
+
+                    [Point2{x: 1, y: 0}, Point2{x: -1, y: 0}, Point2{x: 0, y: 1}, Point2{x: 0, y: -1}]
+                        .into_iter()
+                        .map(|offset| position + offset)
+                        .for_each(|neighbor|
+                        {
+                            let unvisited = regions.get(neighbor) == Some(&usize::MAX);
+
+                            if unvisited && bucket(image[neighbor]) == color
+                            {
+                                regions[neighbor] = region;
+                                queue.push_back(neighbor);
+                            }
+                        });
+                }
+
+                means.push(sum.map(|x| x / count as f32));
+            }
+        }
+
+        Self{regions, means}
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.means.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.means.is_empty()
+    }
+
+    pub fn mean_at(&self, position: Point2<i32>) -> Option<Lab>
+    {
+        self.regions.get(position).map(|region| self.means[*region])
+    }
+}
+
+// evenly spaced quantiles of each lab channel, enough to match other images to it
+#[derive(Debug, Clone)]
+pub struct Histogram
+{
+    quantiles: [Vec<f32>; 3]
+}
+
+impl Histogram
+{
+    const QUANTILES: usize = 256;
+
+    pub fn new(image: &LabImage) -> Self
+    {
+        let quantiles = [0, 1, 2].map(|channel|
+        {
+            let mut values: Vec<f32> = image.pixels().map(|pixel| Self::channel(*pixel, channel))
+                .collect();
+
+            if values.is_empty()
+            {
+                return Vec::new();
+            }
+
+            values.sort_unstable_by(f32::total_cmp);
+
+            (0..Self::QUANTILES).map(|i|
+            {
+                Self::interpolated(&values, i as f32 / (Self::QUANTILES - 1) as f32)
+            }).collect()
+        });
+
+        Self{quantiles}
+    }
+
+    fn channel(pixel: Lab, channel: usize) -> f32
+    {
+        [pixel.l, pixel.a, pixel.b][channel]
+    }
+
+    // fraction goes from 0 (darkest/lowest) to 1
+    fn value_at(&self, channel: usize, fraction: f32) -> f32
+    {
+        Self::interpolated(&self.quantiles[channel], fraction)
+    }
+
+    fn interpolated(sorted: &[f32], fraction: f32) -> f32
+    {
+        let position = fraction.clamp(0.0, 1.0) * (sorted.len() - 1) as f32;
+        let low = position.floor() as usize;
+        let high = (low + 1).min(sorted.len() - 1);
+
+        let t = position - low as f32;
+
+        sorted[low] * (1.0 - t) + sorted[high] * t
+    }
+}
+
+pub type LabImage = GenericImage<Lab>;
+
+impl LabImage
+{
+    pub fn integral(&self) -> IntegralImage
+    {
+        IntegralImage::new(self)
+    }
+
+    pub fn average(&self) -> Lab
+    {
+        let amount = self.pixels().len().max(1) as f32;
+
+        let sum = self.pixels().fold(Lab::zero(), |acc, pixel|
+        {
+            acc.add_scaled(*pixel, 1.0)
+        });
+
+        sum.map(|x| x / amount)
+    }
+
+    // darkens towards the corners, strength 1 makes the very corners black
+    pub fn vignetted(mut self, strength: f32) -> Self
+    {
+        if strength == 0.0
+        {
+            return self;
+        }
+
+        let half = self.size_point().map(|x| x as f32 / 2.0);
+
+        self.pixels_positions_mut().for_each(|(position, pixel)|
+        {
+            let x = (position.x as f32 + 0.5 - half.x) / half.x;
+            let y = (position.y as f32 + 0.5 - half.y) / half.y;
+
+            // 0 in the middle and 1 in the corners
+            let distance = (x * x + y * y) / 2.0;
+
+            pixel.l *= (1.0 - strength * distance).clamp(0.0, 1.0);
+        });
+
+        self
+    }
+
+    // sobel gradient magnitude of the lightness
+    pub fn gradient_magnitude(&self) -> GradientImage
+    {
+        self.gradient_magnitude_between(Point2::repeat(0), self.size_point().map(|x| x as i32))
+    }
+
+    // same as the part of gradient_magnitude from low to high, high is exclusive
+    pub fn gradient_magnitude_between(&self, low: Point2<i32>, high: Point2<i32>) -> GradientImage
+    {
+        let lightness = |position: Point2<i32>|
+        {
+            self.get_edge(position, EdgeMode::Clamp).unwrap().l
+        };
+
+        let size = (high - low).map(|x| x.max(0) as usize);
+
+        GradientImage::from_fn(size.x, size.y, |position|
+        {
+            let position = position + low;
+            let at = |x, y| lightness(position + Point2{x, y});
+
+            let gradient_x = (at(1, -1) + 2.0 * at(1, 0) + at(1, 1))
+                - (at(-1, -1) + 2.0 * at(-1, 0) + at(-1, 1));
+
+            let gradient_y = (at(-1, 1) + 2.0 * at(0, 1) + at(1, 1))
+                - (at(-1, -1) + 2.0 * at(0, -1) + at(1, -1));
+
+            gradient_x.hypot(gradient_y)
+        })
+    }
+
+    pub fn to_rgb(self) -> RgbImage
+    {
+        let threads = thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
+
+        self.to_rgb_in(ColorConversion::default(), threads)
+    }
+
+    pub fn from_rgb_in(value: &Rgb32FImage, conversion: ColorConversion) -> Self
+    {
+        let data = value.pixels().map(|pixel|
+        {
+            conversion.lab_from_rgb(*pixel)
+        }).collect();
+
+        GenericImage::from_raw(data, value.width() as usize, value.height() as usize)
+    }
+
+    pub fn to_rgb_in(self, conversion: ColorConversion, threads: usize) -> RgbImage
+    {
+        let buffer = Self::rgb_bytes(&self.data, conversion, threads);
+
+        RgbImage::from_raw(self.width() as u32, self.height() as u32, buffer).unwrap()
+    }
+
+    fn rgb_bytes(pixels: &[Lab], conversion: ColorConversion, threads: usize) -> Vec<u8>
+    {
+        let mut buffer = vec![0_u8; pixels.len() * 3];
+
+        let convert = |pixels: &[Lab], output: &mut [u8]|
+        {
+            pixels.iter().zip(output.chunks_exact_mut(3)).for_each(|(pixel, output)|
+            {
+                output.copy_from_slice(&colors::quantized(conversion.rgb_from_lab(*pixel)).0);
+            });
+        };
+
+        let chunk = pixels.len().div_ceil(threads.max(1)).max(1);
+
+        if chunk >= pixels.len()
+        {
+            convert(pixels, &mut buffer);
+        } else
+        {
+            thread::scope(|scope|
+            {
+                pixels.chunks(chunk).zip(buffer.chunks_mut(chunk * 3)).for_each(|(pixels, output)|
+                {
+                    scope.spawn(move || convert(pixels, output));
+                });
+            });
+        }
+
+        buffer
+    }
+
+    pub fn to_rgba_in(self, alpha: &GradientImage, conversion: ColorConversion, threads: usize) -> RgbaImage
+    {
+        let rgb = self.to_rgb_in(conversion, threads);
+
+        RgbaImage::from_fn(rgb.width(), rgb.height(), |x, y|
+        {
+            let [r, g, b] = rgb.get_pixel(x, y).0;
+            let alpha = (alpha[Point2{x: x as i32, y: y as i32}] * u8::MAX as f32).round() as u8;
+
+            [r, g, b, alpha].into()
+        })
+    }
+
+    pub fn overlay(
+        mut self,
+        other: &LabaImage,
+        position: Point2<i32>,
+        blending: Blending
+    ) -> (LabImage, Point2<i32>, Point2<i32>)
+    {
+        let mut touched = TouchedBounds::new();
+
+        other.pixels_positions().for_each(|(pixel_position, pixel)|
+        {
+            let position = position + pixel_position;
+            if let Some(this_pixel) = self.get_mut(position)
+            {
+                *this_pixel = this_pixel.blend_with(*pixel, blending);
+
+                touched.touch(position);
+            }
+        });
+
+        let (low, high) = touched.bounds();
+
+        (self, low, high)
+    }
+
+    pub fn overlay_rotated(
+        self,
+        other: &LabaImage,
+        position: Point2<i32>,
+        angle: f32,
+        edge: EdgeMode,
+        sampling: RotationSampling,
+        blending: Blending
+    ) -> (LabImage, Point2<i32>, Point2<i32>)
+    {
+        let quarters = angle / consts::FRAC_PI_2;
+        let turns = quarters.round();
+
+        // exact 90 degree turns dont need any resampling
+        if (quarters - turns).abs() < 0.0001
+        {
+            let turns = turns.rem_euclid(4.0) as u32;
+
+            if turns == 0
+            {
+                return self.overlay(other, position, blending);
+            }
+
+            let rotated = other.rotated_quarters(turns);
+
+            let size_difference = other.size_point().map(|x| x as f32)
+                - rotated.size_point().map(|x| x as f32);
+
+            let position = position + (size_difference / 2.0).map(|x| x.round() as i32);
+
+            return self.overlay(&rotated, position, blending);
+        }
+
+        self.overlay_rotated_sampled(other, position, angle, edge, sampling, blending)
+    }
+
+    fn overlay_rotated_sampled(
+        mut self,
+        other: &LabaImage,
+        position: Point2<i32>,
+        angle: f32,
+        edge: EdgeMode,
+        sampling: RotationSampling,
+        blending: Blending
+    ) -> (LabImage, Point2<i32>, Point2<i32>)
+    {
+        let rotate = |origin: Point2<f32>, position: Point2<i32>, angle: f32|
+        {
+            let position = position.map(|x| x as f32) - origin;
+
+            let a_cos = angle.cos();
+            let a_sin = angle.sin();
+
+            Point2{
+                x: a_cos * position.x - a_sin * position.y,
+                y: a_sin * position.x + a_cos * position.y
+            } + origin
+        };
+
+        let middle = other.size_point().map(|x| x as f32) / 2.0;
+
+        let global_middle = position.map(|x| x as f32) + middle;
+
+        let this_rotate = |position: Point2<i32>|
+        {
+            rotate(global_middle, position, angle)
+        };
+
+        let size = other.size_point().map(|x| x as i32);
+
+        let rotated_ll = this_rotate(position);
+        let rotated_lh = this_rotate(position + Point2{x: 0, ..size});
+        let rotated_hl = this_rotate(position + Point2{y: 0, ..size});
+        let rotated_hh = this_rotate(position + size);
+
+        let rotated = rotated_ll.zip(rotated_lh).zip(rotated_hl).zip(rotated_hh);
+
+        fn select<F>(f: F) -> impl FnMut((((f32, f32), f32), f32)) -> f32
+        where
+            F: Fn(f32, f32) -> f32
+        {
+            move |(((ll, lh), hl), hh)| f(ll, f(lh, f(hl, hh)))
+        }
+
+        // bounding boxes
+        let bb_low = rotated.map(select(f32::min)).map(|x| x.floor() as i32);
+        let bb_high = rotated.map(select(f32::max)).map(|x| x.ceil() as i32);
+
+        let mut touched = TouchedBounds::new();
+
+        self.pixels_between_mut(bb_low, bb_high).for_each(|(pixel_position, pixel)|
+        {
+            let rotated = rotate(global_middle, pixel_position, angle);
+
+            let other_pixel = match sampling
+            {
+                RotationSampling::Nearest =>
+                {
+                    other.get_edge(rotated.map(|x| x.round() as i32) - position, edge).copied()
+                },
+                RotationSampling::Bilinear =>
+                {
+                    other.sample_bilinear(rotated - position.map(|x| x as f32), edge)
+                }
+            };
+
+            if let Some(other_pixel) = other_pixel
+            {
+                *pixel = pixel.blend_with(other_pixel, blending);
+
+                touched.touch(pixel_position);
+            }
+        });
+
+        let (low, high) = touched.bounds();
+
+        (self, low, high)
+    }
+
+    pub fn overlay_stroke_rotated(
+        self,
+        other: &LabaImage,
+        position: Point2<i32>,
+        angle: f32,
+        edge: EdgeMode,
+        sampling: RotationSampling,
+        stroke: &Stroke
+    ) -> (LabImage, Point2<i32>, Point2<i32>)
+    {
+        // grown evenly so it still rotates around the same middle
+        self.overlay_rotated(
+            &stroke.outline(other),
+            position - stroke.width as i32,
+            angle,
+            edge,
+            sampling,
+            Blending::default()
+        )
+    }
+
+    pub fn overlay_shadow_rotated(
+        self,
+        other: &LabaImage,
+        position: Point2<i32>,
+        angle: f32,
+        edge: EdgeMode,
+        sampling: RotationSampling,
+        shadow: &Shadow
+    ) -> (LabImage, Point2<i32>, Point2<i32>)
+    {
+        // shadows always just darken
+        self.overlay_rotated(
+            &shadow.silhouette(other),
+            position + shadow.offset,
+            angle,
+            edge,
+            sampling,
+            Blending::default()
+        )
+    }
+}
+
+// a finished canvas thats only turned into 8 bit pixels when its needed
+pub struct Rendered
+{
+    image: LabImage,
+    alpha: Option<GradientImage>,
+    conversion: ColorConversion,
+    threads: usize
+}
+
+impl Rendered
+{
+    pub fn new(
+        image: LabImage,
+        alpha: Option<GradientImage>,
+        conversion: ColorConversion,
+        threads: usize
+    ) -> Self
+    {
+        Self{image, alpha, conversion, threads}
+    }
+
+    pub fn width(&self) -> usize
+    {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> usize
+    {
+        self.image.height()
+    }
+
+    pub fn has_alpha(&self) -> bool
+    {
+        self.alpha.is_some()
+    }
+
+    // same bytes as the rgb(a) image would have, but only for some rows
+    fn rows_bytes(&self, start: usize, end: usize) -> Vec<u8>
+    {
+        let width = self.width();
+        let pixels = &self.image.data[start * width..end * width];
+
+        let rgb = LabImage::rgb_bytes(pixels, self.conversion, self.threads);
+
+        let Some(alpha) = self.alpha.as_ref() else
+        {
+            return rgb;
+        };
+
+        let alpha = &alpha.data[start * width..end * width];
+
+        rgb.chunks_exact(3).zip(alpha).flat_map(|(rgb, alpha)|
+        {
+            let alpha = (alpha * u8::MAX as f32).round() as u8;
+
+            [rgb[0], rgb[1], rgb[2], alpha]
+        }).collect()
+    }
+
+    // encodes a few rows at a time so the whole 8 bit image never exists at once
+    pub fn write_png(&self, writer: impl Write) -> ImageResult<()>
+    {
+        let io_error = |err: png::EncodingError| ImageError::IoError(err.into());
+
+        let mut encoder = png::Encoder::new(writer, self.width() as u32, self.height() as u32);
+
+        // the same settings the image crate uses by default
+        encoder.set_color(if self.has_alpha() { png::ColorType::Rgba } else { png::ColorType::Rgb });
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Default);
+        encoder.set_filter(png::FilterType::Sub);
+        encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+
+        let mut header = encoder.write_header().map_err(io_error)?;
+        let mut stream = header.stream_writer().map_err(io_error)?;
+
+        let band = (self.threads.max(1) * 16).max(1);
+        for start in (0..self.height()).step_by(band)
+        {
+            let end = (start + band).min(self.height());
+
+            stream.write_all(&self.rows_bytes(start, end))?;
+        }
+
+        stream.finish().map_err(io_error)?;
+
+        header.finish().map_err(io_error)
+    }
+}
+
+impl From<Rendered> for DynamicImage
+{
+    fn from(value: Rendered) -> Self
+    {
+        let (width, height) = (value.width() as u32, value.height() as u32);
+        let bytes = value.rows_bytes(0, value.height());
+
+        if value.has_alpha()
+        {
+            RgbaImage::from_raw(width, height, bytes).unwrap().into()
+        } else
+        {
+            RgbImage::from_raw(width, height, bytes).unwrap().into()
+        }
+    }
+}
+
+impl From<LabaImage> for LabImage
+{
+    fn from(value: LabaImage) -> Self
+    {
+        value.map(|pixel| pixel.no_alpha())
+    }
+}
+
+impl From<RgbImage> for LabImage
+{
+    fn from(value: RgbImage) -> Self
+    {
+        <Self as From<Rgb32FImage>>::from(value.convert())
+    }
+}
+
+impl From<Rgb32FImage> for LabImage
+{
+    fn from(value: Rgb32FImage) -> Self
+    {
+        Self::from_rgb_in(&value, ColorConversion::default())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn interior_error(image: &LabImage, original_width: usize, f: impl Fn(f32) -> f32) -> f32
+    {
+        let scale = original_width as f32 / image.width() as f32;
+
+        (0..image.width()).filter_map(|x|
+        {
+            let source = (x as f32 + 0.5) * scale - 0.5;
+
+            (1.0..=(original_width as f32 - 2.0)).contains(&source).then(||
+            {
+                (image[Point2{x: x as i32, y: 0}].l - f(source)).abs()
+            })
+        }).sum()
+    }
+
+    #[test]
+    fn vignette_darkens_corners()
+    {
+        let image = LabImage::repeat(Lab{l: 50.0, a: 10.0, b: 0.0}, 9, 9);
+
+        let vignetted = image.clone().vignetted(0.5);
+
+        let corner = vignetted[Point2{x: 0, y: 0}];
+        let center = vignetted[Point2{x: 4, y: 4}];
+
+        assert_eq!(center.l, 50.0);
+        assert!(corner.l < 35.0);
+        assert_eq!(corner.a, 10.0);
+
+        let untouched = image.clone().vignetted(0.0);
+        assert!(image.pixels().zip(untouched.pixels()).all(|(a, b)| a.l == b.l));
+    }
+
+    #[test]
+    fn integral_means()
+    {
+        let image = LabImage::from_fn(7, 5, |position|
+        {
+            let Point2{x, y} = position.map(|x| x as f32);
+
+            Lab{l: x * 3.0 + y, a: x * y - 4.0, b: (x - y) * 0.5}
+        });
+
+        let integral = image.integral();
+
+        let brute_force = |low: Point2<i32>, high: Point2<i32>|
+        {
+            let pixels: Vec<_> = image.pixels_between(low, high).map(|(_, pixel)| *pixel).collect();
+
+            let amount = pixels.len() as f32;
+
+            pixels.into_iter().fold(Lab::zero(), |acc, x| acc.add_scaled(x, 1.0 / amount))
+        };
+
+        let regions = [
+            (Point2{x: 0, y: 0}, Point2{x: 7, y: 5}),
+            (Point2{x: 2, y: 1}, Point2{x: 3, y: 2}),
+            (Point2{x: 1, y: 2}, Point2{x: 6, y: 5}),
+            (Point2{x: -3, y: -3}, Point2{x: 4, y: 2})
+        ];
+
+        for (low, high) in regions
+        {
+            let mean = integral.region_mean(low, high).unwrap();
+            let expected = brute_force(low, high);
+
+            assert!(mean.distance(expected) < 0.0001, "{mean:?} {expected:?}");
+        }
+
+        assert!(integral.region_mean(Point2{x: 3, y: 3}, Point2{x: 3, y: 4}).is_none());
+    }
+
+    #[test]
+    fn overlay_bounds()
+    {
+        let lab = Lab{l: 50.0, a: 0.0, b: 0.0};
+
+        let image = LabImage::repeat(lab, 10, 10);
+        let other = LabaImage::repeat(Laba::from(lab), 4, 3);
+
+        let (_, low, high) = image.clone().overlay(&other, Point2{x: 8, y: 2}, Blending::default());
+
+        assert_eq!((low.x, low.y, high.x, high.y), (8, 2, 10, 5));
+
+        let (_, low, high) = image.overlay_rotated(&other, Point2{x: 2, y: 2}, 0.0, EdgeMode::Transparent, RotationSampling::Nearest, Blending::default());
+
+        assert_eq!((low.x, low.y, high.x, high.y), (2, 2, 6, 5));
+    }
+
+    #[test]
+    fn quarter_turns()
+    {
+        let image = GenericImage::from_fn(3, 2, |position| position.x + position.y * 10);
+
+        let rows = |image: &GenericImage<i32>|
+        {
+            (0..image.height() as i32).map(|y|
+            {
+                (0..image.width() as i32).map(|x| image[Point2{x, y}]).collect::<Vec<_>>()
+            }).collect::<Vec<_>>()
+        };
+
+        assert_eq!(rows(&image.rotated_quarters(1)), vec![vec![2, 12], vec![1, 11], vec![0, 10]]);
+        assert_eq!(rows(&image.rotated_quarters(2)), vec![vec![12, 11, 10], vec![2, 1, 0]]);
+        assert_eq!(rows(&image.rotated_quarters(3)), vec![vec![10, 0], vec![11, 1], vec![12, 2]]);
+
+        let back = image.rotated_quarters(1).rotated_quarters(3);
+        assert_eq!(rows(&back), rows(&image));
+    }
+
+    #[test]
+    fn zero_angle_overlay_matches()
+    {
+        let other = LabaImage::from_fn(5, 3, |position|
+        {
+            let l = (position.x * 10 + position.y) as f32;
+
+            Laba{l, a: 0.0, b: 0.0, alpha: (position.x as f32 + 1.0) / 5.0}
+        });
+
+        let image = LabImage::from_fn(12, 12, |position|
+        {
+            Lab{l: position.y as f32, a: 0.0, b: 0.0}
+        });
+
+        for position in [Point2{x: 3, y: 4}, Point2{x: -2, y: 10}, Point2{x: 9, y: -1}]
+        {
+            let (sampled, sampled_low, sampled_high) = image.clone()
+                .overlay_rotated_sampled(&other, position, 0.0, EdgeMode::Transparent, RotationSampling::Nearest, Blending::default());
+
+            let (fast, fast_low, fast_high) = image.clone()
+                .overlay_rotated(&other, position, 0.0, EdgeMode::Transparent, RotationSampling::Nearest, Blending::default());
+
+            assert_eq!((sampled_low.x, sampled_low.y), (fast_low.x, fast_low.y));
+            assert_eq!((sampled_high.x, sampled_high.y), (fast_high.x, fast_high.y));
+
+            sampled.pixels().zip(fast.pixels()).for_each(|(a, b)|
+            {
+                assert_eq!(a.l, b.l);
+            });
+        }
+    }
+
+    #[test]
+    fn bilinear_sampling_borders()
+    {
+        let image = LabaImage::from_fn(2, 2, |position|
+        {
+            Laba{l: (position.x + position.y * 2) as f32, a: 0.0, b: 0.0, alpha: 1.0}
+        });
+
+        let sample = |x, y| image.sample_bilinear(Point2{x, y}, EdgeMode::Transparent);
+
+        assert_eq!(sample(1.0, 1.0).unwrap().l, 3.0);
+        assert_eq!(sample(0.5, 0.0).unwrap().l, 0.5);
+
+        // half of it hangs off the image so its only half opaque but keeps the color
+        let edge = sample(1.5, 0.0).unwrap();
+        assert_eq!((edge.l, edge.alpha), (1.0, 0.5));
+
+        assert!(sample(2.5, 0.0).is_none());
+        assert!(sample(-1.5, -1.5).is_none());
+
+        let clamped = image.sample_bilinear(Point2{x: 1.5, y: 0.0}, EdgeMode::Clamp).unwrap();
+        assert_eq!(clamped.alpha, 1.0);
+    }
+
+    #[test]
+    fn prerotated_matches_overlay()
+    {
+        let other = LabaImage::from_fn(5, 3, |position|
+        {
+            Laba{l: (position.x * 10 + position.y) as f32, a: 0.0, b: 0.0, alpha: 1.0}
+        });
+
+        let image = LabImage::repeat(Lab{l: -1.0, a: 0.0, b: 0.0}, 24, 24);
+        let position = Point2{x: 8, y: 8};
+
+        for angle in [0.5, consts::FRAC_PI_2, 2.0]
+        {
+            let (overlaid, _, _) = image.clone().overlay_rotated(
+                &other,
+                position,
+                angle,
+                EdgeMode::Transparent,
+                RotationSampling::Nearest,
+                Blending::default()
+            );
+
+            let (rotated, offset) = other.rotated(angle, EdgeMode::Transparent, RotationSampling::Nearest);
+            let (blitted, _, _) = image.clone().overlay(&rotated, position + offset, Blending::default());
+
+            overlaid.pixels().zip(blitted.pixels()).for_each(|(a, b)|
+            {
+                assert!((a.l - b.l).abs() < 0.001, "{} != {}", a.l, b.l);
+            });
+        }
+    }
+
+    #[test]
+    fn quarter_turn_overlay_keeps_pixels()
+    {
+        let other = LabaImage::repeat(Laba{l: 1.0, a: 0.0, b: 0.0, alpha: 1.0}, 5, 2);
+        let image = LabImage::repeat(Lab{l: 0.0, a: 0.0, b: 0.0}, 12, 12);
+
+        let (image, low, high) = image.overlay_rotated(
+            &other,
+            Point2{x: 3, y: 4},
+            consts::FRAC_PI_2,
+            EdgeMode::Transparent,
+            RotationSampling::Nearest,
+            Blending::default()
+        );
+
+        let covered: f32 = image.pixels().map(|pixel| pixel.l).sum();
+
+        assert_eq!(covered, 10.0);
+        assert_eq!((high.x - low.x, high.y - low.y), (2, 5));
+    }
+
+    #[test]
+    fn threaded_rgb_matches()
+    {
+        let image = LabImage::from_raw((0..7 * 5).map(|i|
+        {
+            Lab{l: i as f32 * 2.5, a: i as f32 - 10.0, b: 20.0 - i as f32}
+        }).collect(), 7, 5);
+
+        let single = image.clone().to_rgb_in(ColorConversion::default(), 1);
+        let threaded = image.to_rgb_in(ColorConversion::default(), 4);
+
+        assert_eq!(single.into_raw(), threaded.into_raw());
+    }
+
+    #[test]
+    fn segments_split_colors()
+    {
+        let image = LabImage::from_fn(6, 4, |position|
+        {
+            let l = if position.x < 2 || position.y == 3 { 20.0 } else { 70.0 };
+
+            Lab{l, a: 0.0, b: 0.0}
+        });
+
+        let segments = Segments::new(&image, 10.0);
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments.mean_at(Point2{x: 5, y: 3}).unwrap().l, 20.0);
+        assert_eq!(segments.mean_at(Point2{x: 4, y: 1}).unwrap().l, 70.0);
+        assert!(segments.mean_at(Point2{x: 6, y: 0}).is_none());
+    }
+
+    #[test]
+    fn circle_mask()
+    {
+        let pixel = Laba{l: 50.0, a: 0.0, b: 0.0, alpha: 1.0};
+        let image = LabaImage::repeat(pixel, 10, 6).masked(TileShape::Circle);
+
+        let alpha = |x, y| image.get(Point2{x, y}).unwrap().alpha;
+
+        assert_eq!(alpha(5, 3), 1.0);
+        assert_eq!(alpha(0, 0), 0.0);
+        assert_eq!(alpha(0, 3), 0.0);
+        assert_eq!(alpha(3, 0), 1.0);
+
+        let image = LabaImage::repeat(pixel, 10, 6).masked(TileShape::Ellipse);
+
+        assert_eq!(image.get(Point2{x: 0, y: 3}).unwrap().alpha, 1.0);
+    }
+
+    #[test]
+    fn stroke_grows_alpha()
+    {
+        let image = LabaImage::from_fn(3, 3, |position|
+        {
+            let alpha = if position == Point2::repeat(1) { 1.0 } else { 0.0 };
+
+            Laba{l: 50.0, a: 0.0, b: 0.0, alpha}
+        });
+
+        let color = Lab{l: 90.0, a: 0.0, b: 0.0};
+        let outline = Stroke{width: 1, color}.outline(&image);
+
+        assert_eq!(outline.size_point(), Point2::repeat(5));
+
+        let alpha = |x, y| outline.get(Point2{x, y}).unwrap().alpha;
+
+        assert_eq!(alpha(2, 2), 1.0);
+        assert_eq!(alpha(1, 3), 1.0);
+        assert_eq!(alpha(0, 2), 0.0);
+        assert_eq!(alpha(4, 4), 0.0);
+        assert_eq!(outline.get(Point2{x: 1, y: 1}).unwrap().l, 90.0);
+    }
+
+    #[test]
+    fn shadow_is_flat()
+    {
+        let image = LabaImage::from_fn(2, 2, |position|
+        {
+            Laba{l: 20.0 + (position.x + position.y * 2) as f32 * 20.0, a: 10.0, b: -10.0, alpha: 1.0}
+        });
+
+        let canvas = LabImage::repeat(Lab{l: 80.0, a: 0.0, b: 0.0}, 6, 6);
+        let shadow = Shadow{offset: Point2::repeat(1), darkness: 0.5};
+
+        let position = Point2::repeat(1);
+        let (canvas, _, _) = canvas.overlay_shadow_rotated(
+            &image,
+            position,
+            0.0,
+            EdgeMode::Transparent,
+            RotationSampling::Nearest,
+            &shadow
+        );
+
+        let (canvas, _, _) = canvas.overlay_rotated(
+            &image,
+            position,
+            0.0,
+            EdgeMode::Transparent,
+            RotationSampling::Nearest,
+            Blending::default()
+        );
+
+        let pixel = |x, y| canvas[Point2{x, y}];
+
+        // only the offset part of the shadow isnt under the image
+        [(3, 2), (2, 3), (3, 3)].into_iter().for_each(|(x, y)|
+        {
+            let pixel = pixel(x, y);
+
+            assert!((pixel.l - 40.0).abs() < 0.001);
+            assert!(pixel.a.abs() < 0.001 && pixel.b.abs() < 0.001);
+        });
+
+        assert_eq!(pixel(2, 2).l, 80.0);
+        assert_eq!(pixel(1, 1).l, 20.0);
+        assert_eq!(pixel(4, 4).l, 80.0);
+    }
+
+    #[test]
+    fn box_downscale_updates()
+    {
+        let image = LabImage::from_fn(7, 5, |position|
+        {
+            Lab{l: (position.x + position.y * 7) as f32, a: 0.0, b: 0.0}
+        });
+
+        let small = image.box_downscaled(3, 1);
+
+        assert_eq!(small.size_point(), Point2{x: 3, y: 2});
+
+        // first block only has the 2x2 pixels that r inside the image
+        assert_eq!(small[Point2{x: 0, y: 0}].l, (0.0 + 1.0 + 7.0 + 8.0) / 4.0);
+
+        let mut changed = image.clone();
+        changed[Point2{x: 4, y: 1}] = Lab{l: 100.0, a: 0.0, b: 0.0};
+
+        let mut updated = small.clone();
+        updated.update_box_downscaled(&changed, 3, 1, Point2{x: 4, y: 1}, Point2{x: 5, y: 2});
+
+        let lightness = |image: &LabImage| image.pixels().map(|x| x.l).collect::<Vec<_>>();
+
+        assert_eq!(lightness(&updated), lightness(&changed.box_downscaled(3, 1)));
+    }
+
+    #[test]
+    fn bicubic_smoother()
+    {
+        let f = |x: f32| x * x;
+
+        let width = 8;
+        let image = LabImage::from_fn(width, 1, |position|
+        {
+            Lab{l: f(position.x as f32), a: 0.0, b: 0.0}
+        });
+
+        let size = Point2{x: width * 4, y: 1};
+
+        let nearest = interior_error(&image.resized_nearest(size), width, f);
+        let bilinear = interior_error(&image.resized_bilinear(size), width, f);
+        let bicubic = interior_error(&image.resized_bicubic(size), width, f);
+
+        assert!(bilinear < nearest, "bilinear: {bilinear}, nearest: {nearest}");
+        assert!(bicubic < bilinear, "bicubic: {bicubic}, bilinear: {bilinear}");
+    }
+
+    #[test]
+    fn histogram_matching_keeps_order()
+    {
+        let target = LabImage::from_fn(4, 1, |position| Lab{l: 60.0 + position.x as f32, a: 5.0, b: 0.0});
+
+        let image = LabaImage::from_fn(3, 1, |position|
+        {
+            Laba{l: 10.0 - position.x as f32, a: 0.0, b: 0.0, alpha: 1.0}
+        }).histogram_matched(&Histogram::new(&target));
+
+        let l: Vec<f32> = image.pixels().map(|pixel| pixel.l).collect();
+
+        assert_eq!(l, [63.0, 61.5, 60.0]);
+        assert!(image.pixels().all(|pixel| pixel.a == 5.0));
+    }
+
+    #[test]
+    fn streamed_png_matches_image()
+    {
+        let rendered = ||
+        {
+            let image = LabImage::from_fn(7, 40, |position|
+            {
+                Lab{l: position.y as f32 * 2.5, a: position.x as f32 * 10.0 - 30.0, b: 5.0}
+            });
+
+            let alpha = GradientImage::from_fn(7, 40, |position| position.x as f32 / 6.0);
+
+            Rendered::new(image, Some(alpha), ColorConversion::default(), 2)
+        };
+
+        let mut png = Vec::new();
+        rendered().write_png(&mut png).unwrap();
+
+        let decoded = image::load_from_memory(&png).unwrap();
+
+        assert_eq!(decoded.as_bytes(), DynamicImage::from(rendered()).as_bytes());
+    }
+
+    #[test]
+    fn resizing_to_nothing_keeps_a_pixel()
+    {
+        let image = LabImage::repeat(Lab{l: 20.0, a: 0.0, b: 0.0}, 4, 3);
+
+        [ScaleFilter::Nearest, ScaleFilter::Bilinear, ScaleFilter::Bicubic].into_iter().for_each(|filter|
+        {
+            let resized = image.resized(Point2{x: 0, y: 2}, filter);
+
+            assert_eq!(resized.size_point(), Point2{x: 1, y: 2});
+            assert!(resized.pixels().all(|pixel| (pixel.l - 20.0).abs() < 0.001));
+
+            assert_eq!(image.resized(Point2::repeat(0), filter).size_point(), Point2::repeat(1));
+        });
+    }
+
+    #[test]
+    fn image_buffer_roundtrip()
+    {
+        let buffer = image::GrayImage::from_fn(3, 2, |x, y| [(x + y * 3) as u8].into());
+
+        let image = GenericImage::from_image_buffer(&buffer, |pixel| pixel.0[0] as u32 * 10);
+
+        assert_eq!(image.as_raw(), &[0, 10, 20, 30, 40, 50]);
+        assert_eq!(image[Point2{x: 2, y: 1}], 50);
+
+        let back = image.to_image_buffer(|x| image::Luma([(x / 10) as u8]));
+
+        assert_eq!(back, buffer);
+        assert_eq!(image.into_raw().len(), 6);
     }
 }
@@ -0,0 +1,83 @@
+//! Builds collages out of little images by annealing where each one goes.
+//!
+//! Everything re-exported here is the stable surface, it keeps its name and path:
+//!
+//! - [`Collager`] and [`CollagerConfig`] run a whole collage, [`Resume`], [`Checkpoint`],
+//!   [`Progress`] and [`PlacementInfo`] go in and out of it
+//! - [`Annealable`] is implemented by anything that can be optimized, [`Annealer`] and
+//!   [`HillClimber`] optimize it and hand back a [`StateEnergy`] with [`AnnealStats`]
+//! - [`GenericImage`] with [`LabImage`], [`LabaImage`] and [`GradientImage`] hold pixels,
+//!   [`Resamplable`] is what they need for scaling, [`Rendered`] is a finished collage
+//!   that can be streamed into a png or turned into an image
+//! - [`ImageSource`] gives the collager its little images
+//! - [`Rng`] is the rng the annealing traits take, so it doesnt have to be a separate dependency
+//!
+//! The parameters of a single placement (the `Paramable` chain) stay private, theyre
+//! tied to how the collager composites and change along with it. The `config` module is
+//! the command line parsing of the binary and isnt part of the stable surface either.
+
+#![allow(
+    clippy::suspicious_else_formatting,
+    clippy::wrong_self_convention,
+    clippy::excessive_precision,
+    clippy::type_complexity,
+    clippy::unused_unit,
+    clippy::unit_arg
+)]
+
+pub use fastrand::Rng;
+
+pub use point::Point2;
+pub use colors::{Lab, Laba, Oklab, Oklaba, ColorConversion, Blending, srgb_to_linear, linear_to_srgb};
+pub use lab_image::{
+    GenericImage,
+    Resamplable,
+    LabImage,
+    LabaImage,
+    GradientImage,
+    Rendered,
+    IntegralImage,
+    Segments,
+    Histogram,
+    Shadow,
+    Stroke
+};
+pub use config::{
+    EdgeMode,
+    RotationSampling,
+    ScaleFilter,
+    ScaleDecay,
+    AngleMode,
+    TransparencyMode,
+    Background,
+    BackgroundInit,
+    TileShape,
+    BlendMode,
+    Commit,
+    Optimizer,
+    ColorSpace,
+    WhitePoint
+};
+pub use sources::{ImageSource, LazyImages, RotationLocked, RotatedImages};
+pub use collager::{
+    Annealable,
+    Annealer,
+    AnnealStats,
+    HillClimber,
+    StateEnergy,
+    PlacementInfo,
+    Progress,
+    Resume,
+    Checkpoint,
+    CollagerConfig,
+    Collager
+};
+
+#[doc(hidden)]
+pub mod config;
+
+mod point;
+mod colors;
+mod lab_image;
+mod collager;
+mod sources;
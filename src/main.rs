@@ -1,82 +1,789 @@
+#![allow(
+    clippy::suspicious_else_formatting,
+    clippy::wrong_self_convention,
+    clippy::excessive_precision,
+    clippy::type_complexity,
+    clippy::unused_unit,
+    clippy::unit_arg
+)]
+
 use std::{
     fs,
     env,
-    process,
-    fmt::Display
+    mem,
+    thread,
+    num::NonZeroUsize,
+    io::{self, Read, Write, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    hash::{Hash, Hasher},
+    sync::Arc,
+    collections::{HashMap, HashSet, hash_map::DefaultHasher}
+};
+
+use image::{
+    Rgb,
+    DynamicImage,
+    Rgb32FImage,
+    Rgba32FImage,
+    AnimationDecoder,
+    ImageEncoder,
+    ImageResult,
+    imageops::{self, FilterType},
+    codecs::{
+        png::PngDecoder,
+        jpeg::JpegEncoder,
+        gif::GifDecoder,
+        webp::{WebPEncoder, WebPDecoder}
+    }
+};
+
+use annealingcollager::{
+    Point2,
+    Lab,
+    Laba,
+    ColorConversion,
+    srgb_to_linear,
+    linear_to_srgb,
+    LabImage,
+    LabaImage,
+    Rendered,
+    Histogram,
+    Shadow,
+    Stroke,
+    TileShape,
+    AngleMode,
+    ImageSource,
+    LazyImages,
+    RotationLocked,
+    RotatedImages,
+    Resume,
+    CollagerConfig,
+    Collager
 };
 
-use image::imageops::{self, FilterType};
+use annealingcollager::config::{Config, LittleSize, complain};
 
-pub use point::Point2;
-pub use colors::{Lab, Laba};
-pub use lab_image::{LabImage, LabaImage};
+mod glob;
 
-use config::Config;
-use collager::{CollagerConfig, Collager};
+#[cfg(all(feature = "interrupt", unix))]
+mod interrupt;
 
-mod config;
-mod point;
-mod colors;
-mod lab_image;
-mod collager;
 
+enum OutputFormat
+{
+    Png,
+    Jpeg(u8),
+    WebP
+}
 
-fn complain(message: impl Display) -> !
+impl OutputFormat
 {
-    println!("{message}");
+    fn from_path(path: &Path, quality: Option<u32>) -> Self
+    {
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        let format = match extension.as_deref()
+        {
+            // theres no extension to go by when writing to stdout
+            None if is_stdio(path) => Self::Png,
+            Some("png") => Self::Png,
+            Some("jpg" | "jpeg") => Self::Jpeg(quality.unwrap_or(90).clamp(1, 100) as u8),
+            Some("webp") => Self::WebP,
+            Some("avif") => complain("avif output isnt supported by this build of the image crate"),
+            Some(x) => complain(format!("unsupported output extension: {x} (use png, jpg or webp)")),
+            None => complain("output path has no extension (use png, jpg or webp)")
+        };
+
+        if quality.is_some() && !matches!(format, Self::Jpeg(_))
+        {
+            eprintln!("quality only applies to jpg output, saving lossless");
+        }
+
+        format
+    }
+
+    fn save(&self, image: Rendered, path: &Path) -> ImageResult<()>
+    {
+        let writer: Box<dyn Write> = if is_stdio(path)
+        {
+            Box::new(io::stdout().lock())
+        } else
+        {
+            Box::new(fs::File::create(path)?)
+        };
+
+        let writer = BufWriter::new(writer);
+
+        match self
+        {
+            // png doesnt need the whole 8 bit image in memory
+            Self::Png => image.write_png(writer),
+            Self::Jpeg(quality) => Self::encoded(JpegEncoder::new_with_quality(writer, *quality), image),
+            Self::WebP => Self::encoded(WebPEncoder::new_lossless(writer), image)
+        }
+    }
 
-    process::exit(1)
+    fn encoded(encoder: impl ImageEncoder, image: Rendered) -> ImageResult<()>
+    {
+        let image = DynamicImage::from(image);
+
+        encoder.write_image(image.as_bytes(), image.width(), image.height(), image.color())
+    }
 }
 
-fn main()
+// a path of - means stdin or stdout instead of a file
+fn is_stdio(path: &Path) -> bool
 {
-    let config = Config::parse(env::args().skip(1));
+    path.as_os_str() == "-"
+}
+
+fn open_input(path: &str) -> DynamicImage
+{
+    if is_stdio(Path::new(path))
+    {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes).unwrap_or_else(|err|
+        {
+            complain(format!("cant read the input from stdin: {err}"))
+        });
 
-    let images: Vec<_> = fs::read_dir(config.directory).unwrap()
-        .map(Result::unwrap)
-        .filter_map(|entry|
+        image::load_from_memory(&bytes).unwrap_or_else(|err|
         {
-            entry.file_type().unwrap().is_file().then(||
+            complain(format!("cant decode the input from stdin: {err}"))
+        })
+    } else
+    {
+        image::open(path).unwrap()
+    }
+}
+
+fn is_svg(path: &Path) -> bool
+{
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.eq_ignore_ascii_case("svg"))
+        .unwrap_or(false)
+}
+
+// how the directory images get resized before theyre converted
+#[derive(Clone, Copy)]
+struct LittleResize
+{
+    size: Option<LittleSize>,
+    filter: FilterType,
+    // averages linear light instead of the gamma encoded values
+    linear: bool
+}
+
+fn load_little_image(
+    path: impl AsRef<Path>,
+    resize: LittleResize,
+    shape: TileShape,
+    conversion: ColorConversion
+) -> LabaImage
+{
+    little_image(image::open(path).unwrap(), resize, shape, conversion)
+}
+
+fn rgb_mapped(mut image: Rgba32FImage, f: fn(f32) -> f32) -> Rgba32FImage
+{
+    image.pixels_mut().for_each(|pixel|
+    {
+        pixel.0[..3].iter_mut().for_each(|value| *value = f(*value));
+    });
+
+    image
+}
+
+fn matched(image: LabaImage, histogram: Option<&Histogram>) -> LabaImage
+{
+    match histogram
+    {
+        Some(histogram) => image.histogram_matched(histogram),
+        None => image
+    }
+}
+
+fn little_image(
+    image: DynamicImage,
+    resize: LittleResize,
+    shape: TileShape,
+    conversion: ColorConversion
+) -> LabaImage
+{
+    // indexed images r already expanded to their palette by the decoder
+    let has_color = image.color().has_color();
+
+    let image = image.into_rgba32f();
+
+    let image = if let Some(LittleSize{width, height}) = resize.size
+    {
+        // averaging gamma encoded values darkens everything thats shrunk a lot
+        let image = if resize.linear { rgb_mapped(image, srgb_to_linear) } else { image };
+
+        let image = imageops::resize(&image, width, height, resize.filter);
+
+        if resize.linear { rgb_mapped(image, linear_to_srgb) } else { image }
+    } else
+    {
+        image
+    };
+
+    let image = LabaImage::from_rgba_in(&image, conversion).masked(shape);
+
+    if has_color
+    {
+        image
+    } else
+    {
+        // rounding in the color conversion would tint it slightly otherwise
+        image.map(Laba::grayscale)
+    }
+}
+
+// theres no video decoder so animations r the closest thing
+fn animation_frames(path: &str, stride: usize) -> Vec<DynamicImage>
+{
+    let fail = |err: image::ImageError| -> !
+    {
+        complain(format!("cant decode frames of {path}: {err}"))
+    };
+
+    let file = fs::File::open(path).unwrap_or_else(|err|
+    {
+        complain(format!("cant open {path}: {err}"))
+    });
+
+    let file = BufReader::new(file);
+
+    let extension = Path::new(path).extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    let frames = match extension.as_deref()
+    {
+        Some("gif") => GifDecoder::new(file).map(|decoder| decoder.into_frames()),
+        Some("png" | "apng") => PngDecoder::new(file).map(|decoder| decoder.apng().into_frames()),
+        Some("webp") => WebPDecoder::new(file).map(|decoder| decoder.into_frames()),
+        _ => complain(format!("frames can only be read from gif, png or webp animations, not {path}"))
+    }.unwrap_or_else(|err| fail(err));
+
+    frames.step_by(stride.max(1)).map(|frame|
+    {
+        DynamicImage::ImageRgba8(frame.unwrap_or_else(|err| fail(err)).into_buffer())
+    }).collect()
+}
+
+fn listed_paths(list: &str) -> Vec<PathBuf>
+{
+    let text = fs::read_to_string(list).unwrap_or_else(|err|
+    {
+        complain(format!("cant read image list {list}: {err}"))
+    });
+
+    text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).map(|(index, line)|
+    {
+        let path = PathBuf::from(line.trim());
+
+        if !path.is_file()
+        {
+            complain(format!("{list} line {}: {} isnt a file", index + 1, path.display()));
+        }
+
+        path
+    }).collect()
+}
+
+fn rotation_locked(config: &Config, paths: &[PathBuf]) -> Vec<bool>
+{
+    let Some(list) = config.no_rotate_list.as_ref() else
+    {
+        return vec![false; paths.len()];
+    };
+
+    // the same file can be written differently in the list and the sources
+    let canonical = |path: &PathBuf| fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+
+    let listed: HashSet<PathBuf> = listed_paths(list).iter().map(canonical).collect();
+
+    paths.iter().map(|path| listed.contains(&canonical(path))).collect()
+}
+
+fn source_paths(config: &Config) -> Vec<PathBuf>
+{
+    let sources = [&config.directory, &config.image_list, &config.images, &config.frames];
+
+    if sources.iter().all(|source| source.is_none())
+    {
+        complain("a directory, an image list, an images pattern or an animation is needed");
+    }
+
+    // read_dir order depends on the filesystem, sorted so the same seed picks the same images
+    let directory_paths = config.directory.iter().flat_map(|directory|
+    {
+        let mut paths: Vec<PathBuf> = fs::read_dir(directory).unwrap()
+            .map(Result::unwrap)
+            .filter_map(|entry|
             {
-                entry.path()
+                entry.file_type().unwrap().is_file().then(||
+                {
+                    entry.path()
+                })
             })
-        }).map(|path|
+            .collect();
+
+        paths.sort();
+
+        paths
+    });
+
+    let listed = config.image_list.iter().flat_map(|list| listed_paths(list));
+
+    let globbed = config.images.iter().flat_map(|pattern|
+    {
+        let paths = glob::glob(pattern).unwrap_or_else(|err| complain(err));
+
+        if paths.is_empty()
         {
-            image::open(path).unwrap().into_rgba32f()
-        }).map(|image|
+            complain(format!("no images match {pattern}"));
+        }
+
+        paths
+    });
+
+    let paths: Vec<PathBuf> = directory_paths.chain(listed).chain(globbed).collect();
+
+    // checked up front so lazy loading doesnt fail halfway through
+    if let Some(svg) = paths.iter().find(|path| is_svg(path))
+    {
+        complain(format!("{} is an svg, vector sources cant be rasterized by this build", svg.display()));
+    }
+
+    paths
+}
+
+fn pixel_bits(image: &LabaImage) -> impl Iterator<Item=u32> + '_
+{
+    image.pixels().flat_map(|pixel|
+    {
+        [pixel.l, pixel.a, pixel.b, pixel.alpha].map(f32::to_bits)
+    })
+}
+
+// exact duplicates only, after resizing
+// the rotation lock of a duplicate carries over to the image that gets kept
+fn deduplicated(images: Vec<(LabaImage, bool)>) -> Vec<(LabaImage, bool)>
+{
+    let total = images.len();
+
+    let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut unique: Vec<(LabaImage, bool)> = Vec::new();
+
+    for (image, locked) in images
+    {
+        let mut hasher = DefaultHasher::new();
+
+        image.width().hash(&mut hasher);
+        image.height().hash(&mut hasher);
+        pixel_bits(&image).for_each(|bits| bits.hash(&mut hasher));
+
+        let same_hash = seen.entry(hasher.finish()).or_default();
+
+        let duplicate = same_hash.iter().copied().find(|&index|
         {
-            if let Some(little_size) = config.little_size
-            {
-                imageops::resize(&image, little_size, little_size, FilterType::CatmullRom)
-            } else
-            {
-                image
-            }
-        }).collect();
+            let other = &unique[index].0;
+
+            other.size_point() == image.size_point() && pixel_bits(other).eq(pixel_bits(&image))
+        });
+
+        if let Some(index) = duplicate
+        {
+            unique[index].1 |= locked;
+        } else
+        {
+            same_hash.push(unique.len());
+            unique.push((image, locked));
+        }
+    }
+
+    let removed = total - unique.len();
+    if removed > 0
+    {
+        eprintln!("removed {removed} duplicate images");
+    }
+
+    unique
+}
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+// bytes each source takes once its loaded, only the headers of the files get read for this
+fn sources_memory(paths: &[PathBuf], frames: &[DynamicImage], little_size: Option<LittleSize>) -> Vec<u64>
+{
+    let memory = |width: u32, height: u32|
+    {
+        let (width, height) = little_size.map(|size| (size.width, size.height))
+            .unwrap_or((width, height));
+
+        width as u64 * height as u64 * mem::size_of::<Laba>() as u64
+    };
+
+    paths.iter().map(|path|
+    {
+        let (width, height) = image::image_dimensions(path).unwrap_or_else(|err|
+        {
+            complain(format!("cant read {}: {err}", path.display()))
+        });
+
+        memory(width, height)
+    }).chain(frames.iter().map(|frame| memory(frame.width(), frame.height()))).collect()
+}
+
+// the target, the output and a current and a best canvas for every thread
+fn canvases_memory(config: &Config, input_image: &Rgb32FImage) -> u64
+{
+    let threads = config.threads.unwrap_or_else(||
+    {
+        thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1)
+    }).max(1) as u64;
+
+    let side = |x: u32| (x + config.margin * 2) as u64;
+    let pixels = side(input_image.width()) * side(input_image.height());
+
+    pixels * mem::size_of::<Lab>() as u64 * (2 + threads * 2)
+}
+
+// the cardinal and quantized modes only use a few angles, so rotating is cached
+fn rotation_steps(config: &Config) -> Option<u32>
+{
+    if !config.allow_rotation
+    {
+        return None;
+    }
+
+    match config.angle_mode
+    {
+        AngleMode::Continuous => None,
+        AngleMode::Cardinal => Some(4),
+        AngleMode::Quantized => Some(config.angle_steps.max(1))
+    }
+}
+
+// prints the estimated memory and picks how many images can stay loaded to fit the budget
+fn budgeted_lazy_cache(
+    config: &Config,
+    paths: &[PathBuf],
+    frames: &[DynamicImage],
+    input_image: &Rgb32FImage
+) -> Option<usize>
+{
+    let mut sources = sources_memory(paths, frames, config.little_size);
+    let canvases = canvases_memory(config, input_image);
+
+    // any of the biggest images could end up loaded at the same time
+    sources.sort_unstable_by(|a, b| b.cmp(a));
+
+    // as many rotated images r cached as loaded ones, and theyre at most twice as big
+    let copies = if rotation_steps(config).is_some() { 3 } else { 1 };
+
+    let estimate = |capacity: Option<usize>|
+    {
+        canvases + sources.iter().take(capacity.unwrap_or(usize::MAX)).sum::<u64>() * copies
+    };
+
+    let total = estimate(config.lazy_cache);
+
+    eprintln!("estimated memory: {} mb", total.div_ceil(MEGABYTE));
+
+    let Some(budget) = config.max_memory.map(|x| x * MEGABYTE).filter(|budget| total > *budget) else
+    {
+        return config.lazy_cache;
+    };
+
+    if !frames.is_empty()
+    {
+        complain("frames cant be loaded lazily, so they dont fit in the memory budget");
+    }
+
+    let capacity = (1..=sources.len()).rev().find(|capacity| estimate(Some(*capacity)) <= budget)
+        .unwrap_or_else(||
+        {
+            complain(format!(
+                "{} mb is needed even with a single image loaded, lower the max size or little size",
+                estimate(Some(1)).div_ceil(MEGABYTE)
+            ))
+        });
+
+    eprintln!("loading images lazily, at most {capacity} at once to fit in {} mb", budget / MEGABYTE);
+
+    Some(capacity)
+}
+
+fn main()
+{
+    let config = Config::parse(env::args().skip(1));
 
-    let input_image = image::open(config.input).unwrap();
+    let resize_filter = FilterType::from(config.resize_filter);
 
-    let input_image = if let Some(max_size) = config.max_size
+    let conversion = ColorConversion{space: config.color_space, white: config.white_point};
+
+    let input_image = open_input(&config.input);
+
+    // the whole input is used even if its resized later, the quantiles barely change
+    let histogram = config.match_histogram.then(||
+    {
+        Arc::new(Histogram::new(&LabImage::from_rgb_in(&input_image.to_rgb32f(), conversion)))
+    });
+
+    let limit = |max_side: Option<u32>|
     {
-        input_image.resize(max_size, max_size, FilterType::CatmullRom)
+        match (max_side, config.max_size)
+        {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        }
+    };
+
+    let max_width = limit(config.max_width);
+    let max_height = limit(config.max_height);
+
+    let input_image = if max_width.is_some() || max_height.is_some()
+    {
+        // the missing side scales proportionally
+        let max_width = max_width.unwrap_or(u32::MAX);
+        let max_height = max_height.unwrap_or(u32::MAX);
+
+        input_image.resize(max_width, max_height, resize_filter)
     } else
     {
         input_image
     }.into_rgb32f();
 
+    let tile_shape = config.tile_shape;
+    let little_resize = LittleResize{
+        size: config.little_size,
+        filter: resize_filter,
+        linear: config.linear_resize
+    };
+    let load_histogram = histogram.clone();
+    let load = move |path: &PathBuf|
+    {
+        let image = load_little_image(path, little_resize, tile_shape, conversion);
+
+        matched(image, load_histogram.as_deref())
+    };
+
+    let paths = source_paths(&config);
+
+    let frames = config.frames.as_ref().map(|path|
+    {
+        animation_frames(path, config.frame_stride)
+    }).unwrap_or_default();
+
+    let locked = rotation_locked(&config, &paths);
+
+    let lazy_cache = budgeted_lazy_cache(&config, &paths, &frames, &input_image);
+
+    let images: Box<dyn ImageSource> = if let Some(capacity) = lazy_cache
+    {
+        if !frames.is_empty()
+        {
+            complain("frames cant be loaded lazily");
+        }
+
+        // finding duplicates would mean decoding everything up front
+        Box::new(RotationLocked::new(LazyImages::new(paths, capacity, load), locked))
+    } else
+    {
+        let frames = frames.into_iter().map(|frame|
+        {
+            let image = little_image(frame, little_resize, tile_shape, conversion);
+
+            matched(image, histogram.as_deref())
+        });
+
+        let images = paths.iter().map(load).zip(locked)
+            .chain(frames.map(|frame| (frame, false)))
+            .collect();
+
+        let (images, locked): (Vec<_>, Vec<_>) = deduplicated(images).into_iter().unzip();
+
+        Box::new(RotationLocked::new(images.into_iter().map(Arc::new).collect::<Vec<_>>(), locked))
+    };
+
+    let images: Box<dyn ImageSource> = if let Some(steps) = rotation_steps(&config)
+    {
+        let capacity = lazy_cache.unwrap_or(images.len());
+
+        Box::new(RotatedImages::new(images, steps, config.edge_mode, config.rotation_sampling, capacity))
+    } else
+    {
+        images
+    };
+
+    let images = images.as_ref();
+
+
+    let resume = config.resume.map(|path|
+    {
+        let margin = config.margin * 2;
+
+        let image = image::open(path).unwrap().resize_exact(
+            input_image.width() + margin,
+            input_image.height() + margin,
+            resize_filter
+        ).into_rgb32f();
+
+        Resume::Image{
+            image: LabImage::from_rgb_in(&image, conversion),
+            placed: config.resume_placed
+        }
+    });
+
     let collager_config = CollagerConfig{
         steps: config.steps,
+        background_steps: config.background_steps.max(1),
+        background_starts: config.background_starts.max(1),
         amount: config.amount,
+        auto_amount: config.auto_amount,
         starts: config.starts.max(1),
+        greedy_candidates: config.greedy_candidates,
+        tabu_size: config.tabu_size,
+        neighbor_batch: config.neighbor_batch.max(1),
+        commit: config.commit,
+        optimizer: config.optimizer,
         starting_temperature: config.starting_temperature,
+        temperature_floor: config.temperature_floor,
+        acceptance_gamma: config.acceptance_gamma.max(0.0),
+        strict_acceptance: config.strict_acceptance,
+        patience: config.patience,
+        patience_epsilon: config.patience_epsilon.max(0.0),
+        cooling_cycles: config.cooling_cycles.max(1),
+        flat_background: config.flat_background,
+        background_color: config.background_color.map(|color|
+        {
+            conversion.lab_from_rgb(Rgb::from(color))
+        }),
+        background: config.background,
+        background_init: config.background_init,
+        background_cells: config.background_cells.max(1),
+        warm_start: config.warm_start,
+        margin: config.margin,
         allow_scaling: config.allow_scaling,
+        allow_nonuniform_scaling: config.allow_nonuniform_scaling,
+        scale_min: config.scale_min,
+        scale_max: config.scale_max,
+        scale_decay: config.scale_decay,
+        min_placement_pixels: config.min_placement_pixels,
         allow_rotation: config.allow_rotation,
+        angle_mode: config.angle_mode,
+        angle_steps: config.angle_steps.max(1),
         allow_hue: config.allow_hue,
+        allow_blend_modes: config.allow_blend_modes,
         allow_transparency: config.allow_transparency,
-        debug: config.debug
+        transparency_lower_bound: config.transparency_lower_bound.clamp(0.0, 1.0),
+        transparency_mode: config.transparency_mode,
+        min_alpha: config.min_alpha.clamp(0.0, 1.0),
+        max_tile_opacity: config.max_tile_opacity.clamp(0.0, 1.0),
+        scaling_unlock: config.scaling_unlock,
+        rotation_unlock: config.rotation_unlock,
+        hue_unlock: config.hue_unlock,
+        transparency_unlock: config.transparency_unlock,
+        color_weight: config.color_weight,
+        gradient_weight: config.gradient_weight,
+        ssim_weight: config.ssim_weight,
+        segment_weight: config.segment_weight,
+        segment_step: config.segment_step,
+        grid_cap: config.grid_cap,
+        grid_cells: config.grid_cells,
+        overlap_weight: config.overlap_weight,
+        distance_exponent: config.distance_exponent,
+        shadow: config.shadow.then(||
+        {
+            Shadow{
+                offset: Point2::repeat(config.shadow_offset),
+                darkness: config.shadow_darkness
+            }
+        }),
+        stroke: config.stroke.then(||
+        {
+            let color = config.stroke_color.map(Rgb::from).unwrap_or(Rgb([1.0, 1.0, 1.0]));
+
+            Stroke{width: config.stroke_width, color: conversion.lab_from_rgb(color)}
+        }),
+        vignette: config.vignette.clamp(0.0, 1.0),
+        die_cut: config.die_cut,
+        edge_mode: config.edge_mode,
+        rotation_sampling: config.rotation_sampling,
+        scale_filter: config.scale_filter,
+        // a single placement cant stop halfway, so ctrl-c keeps killing it right away
+        #[cfg(all(feature = "interrupt", unix))]
+        stop: (!config.single_placement).then(interrupt::install),
+        #[cfg(not(all(feature = "interrupt", unix)))]
+        stop: None,
+        threads: config.threads,
+        seed: config.seed,
+        checkpoint: config.checkpoint.map(PathBuf::from),
+        tile_size: config.tile_size,
+        tile_overlap: config.tile_overlap,
+        energy_downscale: config.energy_downscale,
+        energy_stride: config.energy_stride.max(1),
+        conversion,
+        energy_report: config.energy_report,
+        stats: config.stats,
+        debug: config.debug,
+        debug_every: config.debug_every.max(1),
+        debug_boxes: config.debug_boxes
     };
 
+    let output_format = OutputFormat::from_path(Path::new(&config.output), config.quality);
+
+    if config.die_cut && matches!(output_format, OutputFormat::Jpeg(_))
+    {
+        complain("jpg has no transparency, die cut output has to be png or webp");
+    }
+
     let collager = Collager::new(collager_config, input_image);
 
-    collager.collage(&images).save(config.output).unwrap();
+    let resume = if let Some(path) = config.load_checkpoint
+    {
+        if resume.is_some()
+        {
+            eprintln!("resume is ignored when loading a checkpoint");
+        }
+
+        let checkpoint = collager.load_checkpoint(Path::new(&path)).unwrap_or_else(|err|
+        {
+            complain(format!("cant load checkpoint {path}: {err}"))
+        });
+
+        eprintln!("continuing after {} placements", checkpoint.placed());
+
+        Some(Resume::Checkpoint(Box::new(checkpoint)))
+    } else
+    {
+        resume
+    };
+
+    let output = if config.single_placement
+    {
+        let (output, info) = collager.best_single_placement(images);
+
+        eprintln!("{info:#?}");
+
+        collager.rendered(output)
+    } else if config.stamp
+    {
+        collager.stamp(images)
+    } else if let Some(cells) = config.voronoi_cells
+    {
+        collager.voronoi(images, cells.max(1), config.voronoi_jitter.clamp(0.0, 1.0))
+    } else
+    {
+        collager.collage(images, resume)
+    };
+
+    output_format.save(output, Path::new(&config.output)).unwrap();
 }
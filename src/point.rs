@@ -14,7 +14,7 @@ use std::{
 };
 
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Point2<T>
 {
     pub x: T,
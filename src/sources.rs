@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    path::PathBuf,
+    f32::consts,
+    sync::{Arc, Mutex}
+};
+
+use crate::{LabaImage, Point2, EdgeMode, RotationSampling};
+
+
+pub trait ImageSource: Sync
+{
+    fn len(&self) -> usize;
+    fn get(&self, index: usize) -> Arc<LabaImage>;
+
+    fn rotatable(&self, _index: usize) -> bool
+    {
+        true
+    }
+
+    // the image turned by step angle steps and where its top left moved, none if
+    // the source doesnt keep rotated images around
+    fn rotated(&self, _index: usize, _step: u32) -> Option<(Arc<LabaImage>, Point2<i32>)>
+    {
+        None
+    }
+
+    fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+}
+
+impl ImageSource for Vec<Arc<LabaImage>>
+{
+    fn len(&self) -> usize
+    {
+        self.as_slice().len()
+    }
+
+    fn get(&self, index: usize) -> Arc<LabaImage>
+    {
+        self[index].clone()
+    }
+}
+
+impl<T: ImageSource + ?Sized> ImageSource for Box<T>
+{
+    fn len(&self) -> usize
+    {
+        self.as_ref().len()
+    }
+
+    fn get(&self, index: usize) -> Arc<LabaImage>
+    {
+        self.as_ref().get(index)
+    }
+
+    fn rotatable(&self, index: usize) -> bool
+    {
+        self.as_ref().rotatable(index)
+    }
+
+    fn rotated(&self, index: usize, step: u32) -> Option<(Arc<LabaImage>, Point2<i32>)>
+    {
+        self.as_ref().rotated(index, step)
+    }
+}
+
+struct Cached<T>
+{
+    value: T,
+    last_used: u64
+}
+
+// keeps the most recently used values around
+struct Cache<K, T>
+{
+    values: HashMap<K, Cached<T>>,
+    capacity: usize,
+    time: u64
+}
+
+impl<K: Hash + Eq + Copy, T: Clone> Cache<K, T>
+{
+    fn new(capacity: usize) -> Self
+    {
+        Self{values: HashMap::new(), capacity: capacity.max(1), time: 0}
+    }
+
+    fn get(&mut self, key: K) -> Option<T>
+    {
+        self.time += 1;
+
+        let time = self.time;
+        self.values.get_mut(&key).map(|cached|
+        {
+            cached.last_used = time;
+
+            cached.value.clone()
+        })
+    }
+
+    fn insert(&mut self, key: K, value: T)
+    {
+        if !self.values.contains_key(&key) && self.values.len() >= self.capacity
+        {
+            let oldest = self.values.iter().min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| *key);
+
+            if let Some(oldest) = oldest
+            {
+                self.values.remove(&oldest);
+            }
+        }
+
+        let last_used = self.time;
+        self.values.insert(key, Cached{value, last_used});
+    }
+}
+
+// decodes images the first time theyre used and keeps the most recently used ones around
+pub struct LazyImages
+{
+    paths: Vec<PathBuf>,
+    load: Box<dyn Fn(&PathBuf) -> LabaImage + Send + Sync>,
+    cache: Mutex<Cache<usize, Arc<LabaImage>>>
+}
+
+impl LazyImages
+{
+    pub fn new(
+        paths: Vec<PathBuf>,
+        capacity: usize,
+        load: impl Fn(&PathBuf) -> LabaImage + Send + Sync + 'static
+    ) -> Self
+    {
+        Self{
+            paths,
+            load: Box::new(load),
+            cache: Mutex::new(Cache::new(capacity))
+        }
+    }
+}
+
+impl ImageSource for LazyImages
+{
+    fn len(&self) -> usize
+    {
+        self.paths.len()
+    }
+
+    fn get(&self, index: usize) -> Arc<LabaImage>
+    {
+        if let Some(image) = self.cache.lock().unwrap().get(index)
+        {
+            return image;
+        }
+
+        // other threads can keep using the cache while this decodes
+        let image = Arc::new((self.load)(&self.paths[index]));
+
+        self.cache.lock().unwrap().insert(index, image.clone());
+
+        image
+    }
+}
+
+// keeps some of the images at their original angle even if rotation is allowed
+pub struct RotationLocked<S>
+{
+    images: S,
+    locked: Vec<bool>
+}
+
+impl<S: ImageSource> RotationLocked<S>
+{
+    pub fn new(images: S, locked: Vec<bool>) -> Self
+    {
+        Self{images, locked}
+    }
+}
+
+impl<S: ImageSource> ImageSource for RotationLocked<S>
+{
+    fn len(&self) -> usize
+    {
+        self.images.len()
+    }
+
+    fn get(&self, index: usize) -> Arc<LabaImage>
+    {
+        self.images.get(index)
+    }
+
+    fn rotatable(&self, index: usize) -> bool
+    {
+        !self.locked.get(index).copied().unwrap_or(false) && self.images.rotatable(index)
+    }
+}
+
+type Rotated = (Arc<LabaImage>, Point2<i32>);
+
+// the images turned by the few angles the cardinal and quantized angle modes use, each
+// angle of an image only gets rotated the first time its placed at it
+pub struct RotatedImages<S>
+{
+    images: S,
+    steps: u32,
+    edge: EdgeMode,
+    sampling: RotationSampling,
+    cache: Mutex<Cache<(usize, u32), Rotated>>
+}
+
+impl<S: ImageSource> RotatedImages<S>
+{
+    pub fn new(
+        images: S,
+        steps: u32,
+        edge: EdgeMode,
+        sampling: RotationSampling,
+        capacity: usize
+    ) -> Self
+    {
+        Self{images, steps: steps.max(1), edge, sampling, cache: Mutex::new(Cache::new(capacity))}
+    }
+}
+
+impl<S: ImageSource> ImageSource for RotatedImages<S>
+{
+    fn len(&self) -> usize
+    {
+        self.images.len()
+    }
+
+    fn get(&self, index: usize) -> Arc<LabaImage>
+    {
+        self.images.get(index)
+    }
+
+    fn rotatable(&self, index: usize) -> bool
+    {
+        self.images.rotatable(index)
+    }
+
+    fn rotated(&self, index: usize, step: u32) -> Option<Rotated>
+    {
+        let key = (index, step % self.steps);
+
+        if let Some(rotated) = self.cache.lock().unwrap().get(key)
+        {
+            return Some(rotated);
+        }
+
+        let angle = key.1 as f32 * ((2.0 * consts::PI) / self.steps as f32);
+        let (image, offset) = self.images.get(index).rotated(angle, self.edge, self.sampling);
+
+        let rotated = (Arc::new(image), offset);
+        self.cache.lock().unwrap().insert(key, rotated.clone());
+
+        Some(rotated)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::Laba;
+
+    #[test]
+    fn evicts_least_recently_used()
+    {
+        static LOADS: AtomicUsize = AtomicUsize::new(0);
+
+        let paths = (0..3).map(|i| PathBuf::from(i.to_string())).collect();
+        let images = LazyImages::new(paths, 2, |path|
+        {
+            LOADS.fetch_add(1, Ordering::Relaxed);
+
+            let l = path.to_str().unwrap().parse().unwrap();
+
+            LabaImage::repeat(Laba{l, a: 0.0, b: 0.0, alpha: 1.0}, 1, 1)
+        });
+
+        let l = |index| images.get(index).pixels().next().unwrap().l;
+
+        assert_eq!(l(0), 0.0);
+        assert_eq!(l(1), 1.0);
+        assert_eq!(l(0), 0.0);
+        assert_eq!(LOADS.load(Ordering::Relaxed), 2);
+
+        // 1 is the oldest now so it gets evicted
+        assert_eq!(l(2), 2.0);
+        assert_eq!(l(0), 0.0);
+        assert_eq!(LOADS.load(Ordering::Relaxed), 3);
+
+        assert_eq!(l(1), 1.0);
+        assert_eq!(LOADS.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn locks_only_listed()
+    {
+        let image = Arc::new(LabaImage::repeat(Laba{l: 0.0, a: 0.0, b: 0.0, alpha: 1.0}, 1, 1));
+        let images = RotationLocked::new(vec![image.clone(), image], vec![false, true]);
+
+        assert!(images.rotatable(0));
+        assert!(!images.rotatable(1));
+    }
+
+    #[test]
+    fn rotates_each_step_once()
+    {
+        let image = LabaImage::from_fn(3, 2, |position|
+        {
+            Laba{l: (position.x + position.y * 3) as f32, a: 0.0, b: 0.0, alpha: 1.0}
+        });
+
+        let images = RotatedImages::new(
+            vec![Arc::new(image.clone())],
+            4,
+            EdgeMode::Transparent,
+            RotationSampling::Nearest,
+            2
+        );
+
+        let (rotated, offset) = images.rotated(0, 1).unwrap();
+        let (expected, expected_offset) = image.rotated(consts::FRAC_PI_2, EdgeMode::Transparent, RotationSampling::Nearest);
+
+        assert_eq!(offset, expected_offset);
+        let values = |image: &LabaImage| image.pixels().map(|pixel| (pixel.l, pixel.alpha)).collect::<Vec<_>>();
+        assert_eq!(values(&rotated), values(&expected));
+
+        assert!(Arc::ptr_eq(&rotated, &images.rotated(0, 1).unwrap().0));
+        assert!(Arc::ptr_eq(&rotated, &images.rotated(0, 5).unwrap().0));
+        assert!(!Arc::ptr_eq(&rotated, &images.rotated(0, 2).unwrap().0));
+    }
+}